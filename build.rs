@@ -1,7 +1,10 @@
 use std::env;
+use std::fmt::Write as _;
 use std::fs;
 use std::path::Path;
 
+use encoding_rs::SHIFT_JIS;
+
 fn main() {
     let out_dir = env::var("OUT_DIR").expect("OUT_DIR environment variable not set");
     
@@ -10,4 +13,78 @@ fn main() {
     
     fs::copy("startup.o", Path::new(&out_dir).join("startup.o"))
         .expect("Failed to copy startup.o to output directory. Make sure startup.o exists in the project root.");
+
+    fs::write(Path::new(&out_dir).join("jis0208.rs"), jis0208_table())
+        .expect("Failed to write the JIS X 0208 table to the output directory.");
+}
+
+/// The number of JIS X 0208 rows in the runtime table. Rows 85 to 94 are
+/// unassigned in the standard and only used for vendor extensions.
+const JIS_ROWS: u8 = 84;
+
+/// Generates `text::sjis`'s tables from `encoding_rs`'s Shift-JIS decoder,
+/// so that the target doesn't need a copy of `encoding_rs`.
+///
+/// The decoding table holds the Unicode code point of every row and cell, in
+/// order, with 0 for unassigned ones. The encoding table holds indices into
+/// the decoding table, sorted by the code points they lead to so that it can
+/// be binary searched. Characters that appear twice keep the lower index, so
+/// that row 2 is preferred over NEC's row 13.
+fn jis0208_table() -> String {
+    let code_points = jis0208_code_points();
+    let mut table = format!("static JIS_X_0208: [u16; {} * 94] = [", JIS_ROWS);
+    for row in code_points.chunks(94) {
+        table.push_str("\n   ");
+        for code_point in row {
+            write!(table, " 0x{:04X},", code_point).unwrap();
+        }
+    }
+    table.push_str("\n];\n");
+
+    let mut encoding: Vec<(u16, u16)> = code_points.iter()
+        .enumerate()
+        .filter(|&(_, &code_point)| code_point != 0)
+        .map(|(index, &code_point)| (code_point, index as u16))
+        .collect();
+    encoding.sort_unstable();
+    encoding.dedup_by_key(|&mut (code_point, _)| code_point);
+    write!(table, "\nstatic JIS_X_0208_ENCODING: [u16; {}] = [", encoding.len()).unwrap();
+    for row in encoding.chunks(16) {
+        table.push_str("\n   ");
+        for (_, index) in row {
+            write!(table, " {},", index).unwrap();
+        }
+    }
+    table.push_str("\n];\n");
+    table
+}
+
+fn jis0208_code_points() -> Vec<u16> {
+    let mut code_points = Vec::with_capacity(JIS_ROWS as usize * 94);
+    for row in 0x21..0x21 + JIS_ROWS {
+        for cell in 0x21..=0x7E {
+            let (lead, trail) = jis_to_sjis(row, cell);
+            let bytes = [lead, trail];
+            let (decoded, error) = SHIFT_JIS.decode_without_bom_handling(&bytes);
+            let mut characters = decoded.chars();
+            let code_point = match (error, characters.next(), characters.next()) {
+                (false, Some(character), None) if (character as u32) < 0x10000 => character as u16,
+                _ => 0,
+            };
+            code_points.push(code_point);
+        }
+    }
+    code_points
+}
+
+fn jis_to_sjis(row: u8, cell: u8) -> (u8, u8) {
+    let lead = ((row - 0x21) >> 1) + 0x81;
+    let lead = if lead > 0x9F { lead + 0x40 } else { lead };
+    let trail = if row & 1 != 0 {
+        let trail = cell + 0x1F;
+        if trail >= 0x7F { trail + 1 } else { trail }
+    } else {
+        cell + 0x7E
+    };
+    (lead, trail)
 }
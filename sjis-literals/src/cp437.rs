@@ -0,0 +1,20 @@
+//! Code page 437, as the target's `text::CP437` encodes it.
+
+include!("../../src/text/cp437_glyphs.rs");
+
+/// Encodes a character, or returns `None` if it isn't in code page 437.
+///
+/// ASCII is passed through, including the control characters, and the symbols
+/// that code page 437 shows for them encode to the same bytes.
+pub fn encode_char(character: char) -> Option<u8> {
+    if (character as u32) < 0x80 {
+        return Some(character as u8);
+    }
+    if character == DELETE_GLYPH {
+        return Some(0x7F);
+    }
+    if let Some(index) = CONTROL_GLYPHS.iter().position(|&c| c == character) {
+        return Some(index as u8 + 0x01);
+    }
+    CP437_UPPER.iter().position(|&c| c == character).map(|index| index as u8 + 0x80)
+}
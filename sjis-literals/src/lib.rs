@@ -1,28 +1,183 @@
+//! Compile-time encoding of string literals for DOS.
+//!
+//! Each macro takes a string literal and expands to a byte string literal, a
+//! `&'static [u8; N]`, so the result can be used in statics and costs nothing
+//! at runtime. Characters that can't be encoded are reported with a
+//! `compile_error!` pointing at the character.
+
+#![feature(proc_macro_span)]
+
 extern crate proc_macro;
 
-use proc_macro::TokenStream;
-use syn::{parse, LitStr};
+mod cp437;
+
+use proc_macro::{TokenStream, TokenTree};
+use proc_macro2::{Literal, Span};
+use quote::quote;
+use syn::{parse, Error, LitStr};
 use encoding_rs::SHIFT_JIS;
 
+/// Encodes a string literal in Shift-JIS.
+///
+/// ```ignore
+/// static GREETING: &[u8] = sjis!("こんにちは");
+/// ```
 #[proc_macro]
 pub fn sjis(input: TokenStream) -> TokenStream {
+    expand(input, Encoding::ShiftJis, None)
+}
+
+/// Encodes a string literal in Shift-JIS and appends a NUL terminator.
+#[proc_macro]
+pub fn sjis_nul(input: TokenStream) -> TokenStream {
+    expand(input, Encoding::ShiftJis, Some(b'\0'))
+}
+
+/// Encodes a string literal in Shift-JIS and appends the `$` terminator that
+/// DOS's print function expects.
+#[proc_macro]
+pub fn sjis_dos(input: TokenStream) -> TokenStream {
+    expand(input, Encoding::ShiftJis, Some(b'$'))
+}
+
+/// Encodes a string literal in code page 437.
+///
+/// ```ignore
+/// static TITLE: &[u8] = cp437!("Größe: 25°");
+/// ```
+#[proc_macro]
+pub fn cp437(input: TokenStream) -> TokenStream {
+    expand(input, Encoding::Cp437, None)
+}
+
+/// Encodes a string literal in code page 437 and appends the `$` terminator
+/// that DOS's print function expects.
+///
+/// ```ignore
+/// dos::print(dos_str!("Hello, world!\r\n").as_ptr());
+/// ```
+#[proc_macro]
+pub fn dos_str(input: TokenStream) -> TokenStream {
+    expand(input, Encoding::Cp437, Some(b'$'))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    ShiftJis,
+    Cp437,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::ShiftJis => "Shift-JIS",
+            Encoding::Cp437 => "code page 437",
+        }
+    }
+
+    /// Appends a character's encoding to `output`, or returns `false` if the
+    /// encoding doesn't have it.
+    fn encode_char(self, character: char, output: &mut Vec<u8>) -> bool {
+        match self {
+            Encoding::ShiftJis => {
+                let mut buffer = [0; 4];
+                let (bytes, _, error) = SHIFT_JIS.encode(character.encode_utf8(&mut buffer));
+                output.extend_from_slice(&bytes);
+                !error
+            }
+            Encoding::Cp437 => cp437::encode_char(character).map(|byte| output.push(byte)).is_some(),
+        }
+    }
+}
+
+/// A character that can't be encoded.
+#[derive(Debug, PartialEq, Eq)]
+struct Rejected {
+    character: char,
+    /// The byte offset of the character in the string.
+    offset: usize,
+    message: String,
+}
+
+/// Encodes a string, appending a terminator if one is given.
+fn encode(string: &str, encoding: Encoding, terminator: Option<u8>) -> Result<Vec<u8>, Rejected> {
+    let mut bytes = Vec::with_capacity(string.len() + 1);
+    for (offset, character) in string.char_indices() {
+        let message = if terminator.map(char::from) == Some(character) {
+            format!("{:?} would terminate the string early", character)
+        } else if !encoding.encode_char(character, &mut bytes) {
+            format!("{:?} is not representable in {}", character, encoding.name())
+        } else {
+            continue;
+        };
+        return Err(Rejected { character, offset, message });
+    }
+    bytes.extend(terminator);
+    Ok(bytes)
+}
+
+fn expand(input: TokenStream, encoding: Encoding, terminator: Option<u8>) -> TokenStream {
+    let token = single_literal(&input);
     let string_literal: LitStr = match parse(input) {
         Ok(literal) => literal,
-        Err(_) => panic!("Something other than a string literal was provided.")
+        Err(error) => return error.to_compile_error().into(),
     };
-    let string = string_literal.value();
-    let (bytes, _, error) = SHIFT_JIS.encode(&string);
-    if error {
-        panic!("The string literal contains a character that is not representable.");
-    }
-    let mut output = String::from("[");
-    for (i, &byte) in bytes.iter().enumerate() {
-        output.push_str(&format!("0x{:X}", byte));
-        if i == 0 {
-            output.push_str("u8");
+    match encode(&string_literal.value(), encoding, terminator) {
+        Ok(bytes) => {
+            let mut literal = Literal::byte_string(&bytes);
+            literal.set_span(string_literal.span());
+            quote!(#literal).into()
         }
-        output.push(',');
+        Err(rejected) => {
+            let span = token
+                .and_then(|token| character_span(&token, &string_literal.value(), &rejected))
+                .unwrap_or_else(|| string_literal.span());
+            Error::new(span, rejected.message).to_compile_error().into()
+        }
+    }
+}
+
+/// Returns the literal token if the input is just that.
+fn single_literal(input: &TokenStream) -> Option<proc_macro::Literal> {
+    let mut tokens = input.clone().into_iter();
+    match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Literal(literal)), None) => Some(literal),
+        _ => None,
+    }
+}
+
+/// Finds the span of a rejected character within its literal.
+///
+/// This only works for plain string literals without escapes, where offsets
+/// in the string are offsets in the source past the opening quote.
+fn character_span(token: &proc_macro::Literal, string: &str, rejected: &Rejected) -> Option<Span> {
+    let source = token.to_string();
+    if source.get(1..source.len() - 1) != Some(string) {
+        return None;
+    }
+    let start = 1 + rejected.offset;
+    token.subspan(start..start + rejected.character.len_utf8()).map(Span::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("Aｱ日", Encoding::ShiftJis, None), Ok(vec![b'A', 0xB1, 0x93, 0xFA]));
+        assert_eq!(encode("", Encoding::ShiftJis, Some(b'\0')), Ok(vec![0]));
+        assert_eq!(encode("ü$", Encoding::Cp437, None), Ok(vec![0x81, b'$']));
+    }
+
+    #[test]
+    fn test_rejects() {
+        let rejected = encode("aé€", Encoding::ShiftJis, None).unwrap_err();
+        assert_eq!((rejected.character, rejected.offset), ('é', 1));
+        let rejected = encode("€", Encoding::Cp437, None).unwrap_err();
+        assert_eq!(rejected.message, "'€' is not representable in code page 437");
+        let rejected = encode("cost $5", Encoding::Cp437, Some(b'$')).unwrap_err();
+        assert_eq!((rejected.character, rejected.offset), ('$', 5));
+        assert!(encode("a\0b", Encoding::ShiftJis, Some(b'\0')).is_err());
     }
-    output.push(']');
-    output.parse().unwrap()
-}
\ No newline at end of file
+}
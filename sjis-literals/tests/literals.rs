@@ -0,0 +1,26 @@
+use sjis_literals::{cp437, dos_str, sjis, sjis_dos, sjis_nul};
+
+static GREETING: &[u8] = sjis!("こんにちは");
+static EMPTY: &[u8; 0] = sjis!("");
+const TITLE: &[u8; 5] = cp437!("Größe");
+
+#[test]
+fn test_sjis() {
+    assert_eq!(GREETING, &[0x82, 0xB1, 0x82, 0xF1, 0x82, 0xC9, 0x82, 0xBF, 0x82, 0xCD]);
+    assert!(EMPTY.is_empty());
+    assert_eq!(sjis!("ｱA\r\n"), &[0xB1, b'A', b'\r', b'\n']);
+}
+
+#[test]
+fn test_sjis_terminated() {
+    assert_eq!(sjis_nul!("日本"), &[0x93, 0xFA, 0x96, 0x7B, 0x00]);
+    assert_eq!(sjis_dos!("日本"), &[0x93, 0xFA, 0x96, 0x7B, b'$']);
+    assert_eq!(sjis_dos!(""), b"$");
+}
+
+#[test]
+fn test_cp437() {
+    assert_eq!(TITLE, &[b'G', b'r', 0x94, 0xE1, b'e']);
+    assert_eq!(cp437!("☺\u{2302}"), &[0x01, 0x7F]);
+    assert_eq!(dos_str!("\nPanic!"), b"\nPanic!$");
+}
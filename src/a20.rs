@@ -0,0 +1,254 @@
+//! The A20 address line
+//!
+//! The 8086 could only address 1 MiB, and addresses past the end wrapped
+//! around to the start. Later processors can address more, but some DOS
+//! programs relied on the wraparound, so the 21st address line is gated and
+//! starts off disabled. Memory above 1 MiB, including the high memory area
+//! at FFFF:0010, can only be reached once it is enabled.
+//!
+//! There is no single way to enable it. [`enable`] tries each way that the
+//! platform has until the wraparound goes away, starting with the XMS driver
+//! when one is loaded, since it knows the machine best and keeps track of
+//! who else needs A20. [`restore`] disables it again the same way before the
+//! program exits.
+
+#![allow(dead_code)]
+
+#[cfg(feature = "ibm")]
+use core::arch::asm;
+use core::ptr::{addr_of, read_volatile};
+use crate::far::FarPtr;
+use crate::interrupts;
+use crate::platform::{self, Platform};
+use crate::port;
+use crate::xms;
+
+/// A way of enabling A20.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// It was enabled already, by DOS, a driver or the BIOS.
+    AlreadyEnabled,
+    /// The XMS driver's local enable function.
+    Xms,
+    /// The IBM BIOS's INT 15h function 2401h.
+    Bios,
+    /// The "fast A20" bit of IBM system control port 92h.
+    FastGate,
+    /// The output port of the IBM AT's 8042 keyboard controller.
+    KeyboardController,
+    /// The PC-98's A20 gate port F2h.
+    Pc98Gate,
+    /// The PC-98's A20 control port F6h, for machines where F2h does nothing.
+    Pc98Control,
+}
+
+/// How many times to test for wraparound after trying a method, as the
+/// keyboard controller takes a while to act.
+const SETTLE_ATTEMPTS: u32 = 0x1000;
+
+/// How many times to poll the keyboard controller before giving up.
+const KEYBOARD_CONTROLLER_TIMEOUT: u32 = 0x10000;
+
+const KBC_DATA_PORT: u16 = 0x60;
+const KBC_STATUS_PORT: u16 = 0x64;
+const KBC_READ_OUTPUT_PORT: u8 = 0xD0;
+const KBC_WRITE_OUTPUT_PORT: u8 = 0xD1;
+const KBC_DISABLE_KEYBOARD: u8 = 0xAD;
+const KBC_ENABLE_KEYBOARD: u8 = 0xAE;
+
+static mut METHOD: Option<Method> = None;
+
+/// Returns whether A20 is enabled, by checking whether a write to low memory
+/// shows up 1 MiB higher.
+///
+/// The byte used is in the interrupt vector for INT FFh, which nothing calls
+/// while interrupts are disabled, and it is put back afterwards.
+pub fn is_enabled() -> bool {
+    let low = FarPtr::<u8>::new(0x0000, 0x03FC);
+    let high = FarPtr::<u8>::new(0xFFFF, 0x040C);
+    interrupts::without_interrupts(|| {
+        let original = low.read();
+        if high.read() != original {
+            return true;
+        }
+        low.write(!original);
+        let wrapped = high.read() == !original;
+        low.write(original);
+        !wrapped
+    })
+}
+
+/// Returns the method that last enabled A20, if any did.
+pub fn method() -> Option<Method> {
+    unsafe { read_volatile(addr_of!(METHOD)) }
+}
+
+/// Enables A20 with the first method that works.
+///
+/// Once a method has worked, later calls return it without trying again, so
+/// that [`restore`] still knows how A20 was enabled.
+///
+/// # Returns
+///
+/// The method that worked, or `None` if A20 is still disabled
+pub fn enable() -> Option<Method> {
+    if let Some(method) = method() {
+        return Some(method);
+    }
+    let method = try_methods();
+    unsafe { METHOD = method; }
+    method
+}
+
+/// Disables A20 again if [`enable`] enabled it, using the same method.
+pub fn restore() {
+    let method = method();
+    unsafe { METHOD = None; }
+    match method {
+        Some(Method::Xms) => {
+            let _ = xms::disable_a20();
+        }
+        #[cfg(feature = "ibm")]
+        Some(Method::Bios) => unsafe {
+            asm!(
+                "int 15h",
+                inout("ax") 0x2400u16 => _,
+            );
+        },
+        #[cfg(feature = "ibm")]
+        Some(Method::FastGate) => unsafe {
+            let value = port::inb(0x92);
+            port::outb(0x92, value & !0x03);
+        },
+        #[cfg(feature = "ibm")]
+        Some(Method::KeyboardController) => {
+            set_with_keyboard_controller(false);
+        }
+        // F2h can only open the gate, so it is closed through F6h either way.
+        #[cfg(feature = "pc98")]
+        Some(Method::Pc98Gate) | Some(Method::Pc98Control) => unsafe {
+            port::outb(0xF6, 0x03);
+        },
+        _ => {}
+    }
+}
+
+fn try_methods() -> Option<Method> {
+    if is_enabled() {
+        return Some(Method::AlreadyEnabled);
+    }
+    if xms::enable_a20().is_ok() {
+        if settled() {
+            return Some(Method::Xms);
+        }
+        // Balance the driver's count of local enables.
+        let _ = xms::disable_a20();
+    }
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => {
+            if enable_with_bios() && settled() {
+                return Some(Method::Bios);
+            }
+            enable_with_fast_gate();
+            if settled() {
+                return Some(Method::FastGate);
+            }
+            if set_with_keyboard_controller(true) && settled() {
+                return Some(Method::KeyboardController);
+            }
+        }
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => {
+            // Any write to F2h opens the gate.
+            unsafe { port::outb(0xF2, 0x00) };
+            if settled() {
+                return Some(Method::Pc98Gate);
+            }
+            // Writing 2 to F6h opens it, and 3 closes it.
+            unsafe { port::outb(0xF6, 0x02) };
+            if settled() {
+                return Some(Method::Pc98Control);
+            }
+        }
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+    None
+}
+
+/// Tests for wraparound repeatedly until A20 is enabled or it seems that it
+/// won't be.
+fn settled() -> bool {
+    (0..SETTLE_ATTEMPTS).any(|_| is_enabled())
+}
+
+#[cfg(feature = "ibm")]
+fn enable_with_bios() -> bool {
+    let result: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "int 15h",
+            "sbb cx, cx",
+            inout("ax") 0x2401u16 => result,
+            out("cx") failed,
+        );
+    }
+    failed == 0 && result >> 8 == 0
+}
+
+#[cfg(feature = "ibm")]
+fn enable_with_fast_gate() {
+    unsafe {
+        let value = port::inb(0x92);
+        // Bit 0 resets the machine, so it must be written as 0.
+        if value & 0x02 == 0 {
+            port::outb(0x92, (value | 0x02) & !0x01);
+        }
+    }
+}
+
+/// Sets or clears the A20 bit of the keyboard controller's output port,
+/// leaving the rest of it as it was.
+#[cfg(feature = "ibm")]
+fn set_with_keyboard_controller(enabled: bool) -> bool {
+    // Keys pressed in the middle would be read as the output port's value, so
+    // the keyboard is disabled meanwhile.
+    interrupts::without_interrupts(|| unsafe {
+        let done = set_keyboard_controller_a20(enabled).is_some();
+        let _ = send_to_keyboard_controller(KBC_STATUS_PORT, KBC_ENABLE_KEYBOARD);
+        done
+    })
+}
+
+#[cfg(feature = "ibm")]
+unsafe fn set_keyboard_controller_a20(enabled: bool) -> Option<()> {
+    send_to_keyboard_controller(KBC_STATUS_PORT, KBC_DISABLE_KEYBOARD)?;
+    send_to_keyboard_controller(KBC_STATUS_PORT, KBC_READ_OUTPUT_PORT)?;
+    wait_for_keyboard_controller(|status| status & 0x01 != 0)?;
+    let output = port::inb(KBC_DATA_PORT);
+    send_to_keyboard_controller(KBC_STATUS_PORT, KBC_WRITE_OUTPUT_PORT)?;
+    let output = if enabled { output | 0x02 } else { output & !0x02 };
+    send_to_keyboard_controller(KBC_DATA_PORT, output)?;
+    wait_for_keyboard_controller(|status| status & 0x02 == 0)
+}
+
+/// Writes a command or data byte once the controller's input buffer is empty.
+#[cfg(feature = "ibm")]
+unsafe fn send_to_keyboard_controller(port: u16, value: u8) -> Option<()> {
+    wait_for_keyboard_controller(|status| status & 0x02 == 0)?;
+    port::outb(port, value);
+    Some(())
+}
+
+/// Polls the controller's status until `ready` is true, or gives up after
+/// [`KEYBOARD_CONTROLLER_TIMEOUT`] tries.
+#[cfg(feature = "ibm")]
+unsafe fn wait_for_keyboard_controller(ready: impl Fn(u8) -> bool) -> Option<()> {
+    if (0..KEYBOARD_CONTROLLER_TIMEOUT).any(|_| ready(port::inb(KBC_STATUS_PORT))) {
+        Some(())
+    } else {
+        None
+    }
+}
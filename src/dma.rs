@@ -0,0 +1,94 @@
+//! Intel 8237 DMA controller programming for the 8-bit channels (0-3)
+//!
+//! The controller works with 20-bit physical addresses split into a 16-bit
+//! offset and a page register, and cannot carry from the offset into the page.
+//! A single transfer therefore may not cross a 64 KiB physical boundary.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use crate::port::outb;
+
+const MASK_PORT: u16 = 0x0A;
+const MODE_PORT: u16 = 0x0B;
+const CLEAR_FLIP_FLOP_PORT: u16 = 0x0C;
+
+const ADDRESS_PORTS: [u16; 4] = [0x00, 0x02, 0x04, 0x06];
+const COUNT_PORTS: [u16; 4] = [0x01, 0x03, 0x05, 0x07];
+const PAGE_PORTS: [u16; 4] = [0x87, 0x83, 0x81, 0x82];
+
+/// The direction and repetition of a transfer.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Memory to device, stopping at the end of the buffer.
+    SingleCycleRead,
+    /// Memory to device, restarting from the beginning of the buffer forever.
+    AutoInitRead,
+    /// Device to memory, stopping at the end of the buffer.
+    SingleCycleWrite,
+    /// Device to memory, restarting from the beginning of the buffer forever.
+    AutoInitWrite,
+}
+
+impl Mode {
+    fn bits(self) -> u8 {
+        // Single transfer mode, address increment.
+        match self {
+            Mode::SingleCycleWrite => 0x44,
+            Mode::SingleCycleRead => 0x48,
+            Mode::AutoInitWrite => 0x54,
+            Mode::AutoInitRead => 0x58,
+        }
+    }
+}
+
+/// Returns the physical address of a near pointer in the data segment.
+pub fn physical_address(pointer: *const u8) -> u32 {
+    let segment: u16;
+    unsafe {
+        asm!(
+            "mov {0:x}, ds",
+            out(reg) segment,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    ((segment as u32) << 4) + (pointer as u32 & 0xFFFF)
+}
+
+/// Returns how many bytes can be transferred from a physical address before
+/// reaching the next 64 KiB boundary.
+pub fn bytes_until_boundary(address: u32) -> u32 {
+    0x1_0000 - (address & 0xFFFF)
+}
+
+/// Stops a channel from responding to requests.
+pub fn mask(channel: u8) {
+    unsafe { outb(MASK_PORT, 0x04 | (channel & 0x03)); }
+}
+
+/// Lets a channel respond to requests again.
+pub fn unmask(channel: u8) {
+    unsafe { outb(MASK_PORT, channel & 0x03); }
+}
+
+/// Programs a channel to transfer `length` bytes starting at a physical
+/// address, then unmasks it.
+///
+/// # Safety
+///
+/// The memory must stay valid for as long as the device may transfer to or
+/// from it, and the range must not cross a 64 KiB physical boundary.
+pub unsafe fn start(channel: u8, mode: Mode, address: u32, length: u16) {
+    let index = (channel & 0x03) as usize;
+    let count = length.wrapping_sub(1);
+    mask(channel);
+    outb(CLEAR_FLIP_FLOP_PORT, 0);
+    outb(MODE_PORT, mode.bits() | channel & 0x03);
+    outb(ADDRESS_PORTS[index], address as u8);
+    outb(ADDRESS_PORTS[index], (address >> 8) as u8);
+    outb(PAGE_PORTS[index], (address >> 16) as u8);
+    outb(CLEAR_FLIP_FLOP_PORT, 0);
+    outb(COUNT_PORTS[index], count as u8);
+    outb(COUNT_PORTS[index], (count >> 8) as u8);
+    unmask(channel);
+}
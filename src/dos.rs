@@ -1,10 +1,12 @@
 use core::arch::asm;
+use crate::far::FarPtr;
 
 /// Prints a null-terminated string using DOS interrupt 21h.
 ///
 /// # Arguments
 ///
 /// * `s` - Pointer to a null-terminated string
+#[allow(dead_code)]
 pub fn print(s: *const u8) {
     unsafe {
         asm!(
@@ -20,6 +22,7 @@ pub fn print(s: *const u8) {
 /// # Arguments
 ///
 /// * `c` - The character to print
+#[allow(dead_code)]
 pub fn print_character(c: u8) {
     unsafe {
         asm!(
@@ -30,11 +33,27 @@ pub fn print_character(c: u8) {
     }
 }
 
+/// Writes a single character straight to the console device using DOS
+/// interrupt 29h, which ignores any redirection of standard output.
+///
+/// # Arguments
+///
+/// * `c` - The character to write
+pub fn console_character(c: u8) {
+    unsafe {
+        asm!(
+            "int 29h",
+            in("al") c,
+        );
+    }
+}
+
 /// Gets keyboard input without blocking.
 ///
 /// # Returns
 ///
 /// The scan code of the pressed key, or 0 if no key is pressed
+#[allow(dead_code)]
 pub fn get_keyboard_input() -> u8 {
     let code;
     unsafe {
@@ -56,11 +75,39 @@ pub fn get_keyboard_input() -> u8 {
     code
 }
 
+/// Gets keyboard input without blocking, including the character typed.
+///
+/// # Returns
+///
+/// The scan code in the high byte and the character in the low byte, or
+/// `None` if no key is pressed
+#[allow(dead_code)]
+pub fn get_key() -> Option<u16> {
+    let key: u16;
+    let available: u8;
+    unsafe {
+        asm!(
+            "mov ah, 01h",
+            "int 16h",
+            "mov cl, 0",
+            "jz 2f",
+            "mov ah, 00h",
+            "int 16h",
+            "mov cl, 1",
+            "2:",
+            out("ax") key,
+            out("cl") available,
+        );
+    }
+    if available != 0 { Some(key) } else { None }
+}
+
 /// Sets the video mode using BIOS interrupt 10h.
 ///
 /// # Arguments
 ///
 /// * `mode` - The video mode to set (e.g., 0x03 for text, 0x13 for VGA graphics)
+#[allow(dead_code)]
 pub fn set_video_mode(mode: u8) {
     unsafe {
         asm!(
@@ -70,14 +117,396 @@ pub fn set_video_mode(mode: u8) {
     }
 }
 
+/// Gets the time of day using DOS interrupt 21h.
+///
+/// # Returns
+///
+/// The hours, minutes, seconds and hundredths of a second
+#[allow(dead_code)]
+pub fn get_time() -> (u8, u8, u8, u8) {
+    let cx: u16;
+    let dx: u16;
+    unsafe {
+        asm!(
+            "int 21h",
+            inout("ax") 0x2C00 => _,
+            out("cx") cx,
+            out("dx") dx,
+        );
+    }
+    ((cx >> 8) as u8, cx as u8, (dx >> 8) as u8, dx as u8)
+}
+
+/// Gets the active code page using DOS interrupt 21h.
+///
+/// # Returns
+///
+/// The code page number, or `None` if the DOS version doesn't support code
+/// pages
+pub fn get_code_page() -> Option<u16> {
+    let code_page: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "int 21h",
+            "mov cx, bx",
+            "sbb dx, dx",
+            "pop bx",
+            inout("ax") 0x6601 => _,
+            out("cx") code_page,
+            out("dx") failed,
+        );
+    }
+    if failed == 0 { Some(code_page) } else { None }
+}
+
+/// Writes bytes to a file or device handle using DOS interrupt 21h.
+///
+/// # Arguments
+///
+/// * `handle` - The handle to write to, such as 1 for standard output
+/// * `bytes` - The bytes to write
+///
+/// # Returns
+///
+/// The number of bytes written, which is less than requested if the disk is
+/// full, or the DOS error code
+#[allow(dead_code)]
+pub fn write_handle(handle: u16, bytes: &[u8]) -> Result<u16, u16> {
+    let result: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov bx, di",
+            "int 21h",
+            "pop bx",
+            "sbb di, di",
+            inout("ax") 0x4000u16 => result,
+            inout("di") handle => failed,
+            in("cx") bytes.len() as u16,
+            in("dx") bytes.as_ptr(),
+        );
+    }
+    if failed == 0 { Ok(result) } else { Err(result) }
+}
+
+/// Reads bytes from a file or device handle using DOS interrupt 21h.
+///
+/// # Arguments
+///
+/// * `handle` - The handle to read from, such as 0 for standard input
+/// * `buffer` - Where to put the bytes
+///
+/// # Returns
+///
+/// The number of bytes read, which is 0 at the end of the file, or the DOS
+/// error code
+#[allow(dead_code)]
+pub fn read_handle(handle: u16, buffer: &mut [u8]) -> Result<u16, u16> {
+    let result: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov bx, di",
+            "int 21h",
+            "pop bx",
+            "sbb di, di",
+            inout("ax") 0x3F00u16 => result,
+            inout("di") handle => failed,
+            in("cx") buffer.len().min(0xFFFF) as u16,
+            in("dx") buffer.as_mut_ptr(),
+        );
+    }
+    if failed == 0 { Ok(result) } else { Err(result) }
+}
+
+/// Gets information about a handle using the IOCTL function of DOS interrupt
+/// 21h.
+///
+/// # Arguments
+///
+/// * `handle` - The handle to query
+///
+/// # Returns
+///
+/// The device information word, or the DOS error code
+#[allow(dead_code)]
+pub fn get_device_information(handle: u16) -> Result<u16, u16> {
+    let result: u16;
+    let information: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov bx, di",
+            "int 21h",
+            "pop bx",
+            "sbb di, di",
+            inout("ax") 0x4400u16 => result,
+            inout("di") handle => failed,
+            out("dx") information,
+        );
+    }
+    if failed == 0 { Ok(information) } else { Err(result) }
+}
+
+/// Allocates a block of conventional memory using DOS interrupt 21h.
+///
+/// # Arguments
+///
+/// * `paragraphs` - The size of the block in 16-byte paragraphs
+///
+/// # Returns
+///
+/// The segment of the block, or the DOS error code and the size of the largest
+/// free block in paragraphs
+#[allow(dead_code)]
+pub fn allocate_memory(paragraphs: u16) -> Result<u16, (u16, u16)> {
+    let result: u16;
+    let largest: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov bx, di",
+            "int 21h",
+            "mov di, bx",
+            "pop bx",
+            "sbb cx, cx",
+            inout("ax") 0x4800u16 => result,
+            inout("di") paragraphs => largest,
+            out("cx") failed,
+        );
+    }
+    if failed == 0 { Ok(result) } else { Err((result, largest)) }
+}
+
+/// Frees a block of memory allocated by [`allocate_memory`] using DOS
+/// interrupt 21h.
+///
+/// # Arguments
+///
+/// * `segment` - The segment of the block
+///
+/// # Returns
+///
+/// The DOS error code if the block couldn't be freed
+#[allow(dead_code)]
+pub fn free_memory(segment: u16) -> Result<(), u16> {
+    let result: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push es",
+            "mov es, dx",
+            "int 21h",
+            "pop es",
+            "sbb cx, cx",
+            inout("ax") 0x4900u16 => result,
+            in("dx") segment,
+            out("cx") failed,
+        );
+    }
+    if failed == 0 { Ok(()) } else { Err(result) }
+}
+
+/// Grows or shrinks a block of memory using DOS interrupt 21h.
+///
+/// # Arguments
+///
+/// * `segment` - The segment of the block, which may be the program's own
+/// * `paragraphs` - The new size of the block in 16-byte paragraphs
+///
+/// # Returns
+///
+/// The DOS error code and the largest size the block could have in
+/// paragraphs if it couldn't be resized
+#[allow(dead_code)]
+pub fn resize_memory(segment: u16, paragraphs: u16) -> Result<(), (u16, u16)> {
+    let result: u16;
+    let largest: u16;
+    let failed: u16;
+    unsafe {
+        asm!(
+            "push es",
+            "push bx",
+            "mov es, dx",
+            "mov bx, di",
+            "int 21h",
+            "mov di, bx",
+            "pop bx",
+            "pop es",
+            "sbb cx, cx",
+            inout("ax") 0x4A00u16 => result,
+            inout("di") paragraphs => largest,
+            in("dx") segment,
+            out("cx") failed,
+        );
+    }
+    if failed == 0 { Ok(()) } else { Err((result, largest)) }
+}
+
 /// Exits the program and returns to DOS.
-pub fn exit() {
+pub fn exit() -> ! {
+    unsafe {
+        asm!(
+            "int 21h",
+            in("ax") 0x4C00,
+            options(noreturn),
+        );
+    }
+}
+
+/// Gets the handler an interrupt vector points to using DOS interrupt 21h.
+///
+/// # Returns
+///
+/// The segment and offset of the handler
+#[allow(dead_code)]
+pub fn get_interrupt_vector(vector: u8) -> (u16, u16) {
+    let segment;
+    let offset;
     unsafe {
         asm!(
+            "push es",
+            "push bx",
             "int 21h",
-            inout("ax") 0x4C00 => _,
+            "mov cx, es",
+            "mov dx, bx",
+            "pop bx",
+            "pop es",
+            inout("ax") 0x3500u16 | vector as u16 => _,
+            out("cx") segment,
+            out("dx") offset,
         );
     }
+    (segment, offset)
+}
+
+/// Points an interrupt vector at a handler using DOS interrupt 21h.
+///
+/// # Arguments
+///
+/// * `vector` - The interrupt number
+/// * `segment` - The segment of the handler
+/// * `offset` - The offset of the handler
+#[allow(dead_code)]
+pub fn set_interrupt_vector(vector: u8, segment: u16, offset: u16) {
+    unsafe {
+        asm!(
+            "push ds",
+            "mov ds, cx",
+            "int 21h",
+            "pop ds",
+            inout("ax") 0x2500u16 | vector as u16 => _,
+            in("cx") segment,
+            in("dx") offset,
+        );
+    }
+}
+
+/// Gets the program's code segment, which in a .COM program is also the
+/// segment of its PSP, data and stack.
+#[allow(dead_code)]
+pub fn code_segment() -> u16 {
+    let segment: u16;
+    unsafe {
+        asm!(
+            "mov {0:x}, cs",
+            out(reg) segment,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    segment
+}
+
+/// Looks up a variable in the program's environment block and copies its
+/// value into `buffer`, truncating it if it does not fit.
+///
+/// # Returns
+///
+/// The number of bytes copied, or `None` if the variable is not set
+#[allow(dead_code)]
+pub fn environment_variable(name: &str, buffer: &mut [u8]) -> Option<usize> {
+    // The PSP, which DS points at in a .COM program, holds the segment of the
+    // environment block at offset 2Ch.
+    let segment = unsafe { core::ptr::read_volatile(0x2C as *const u16) };
+    let name = name.as_bytes();
+    let mut offset: u16 = 0;
+    // The block is a list of NUL-terminated NAME=VALUE strings, ending with
+    // an empty string.
+    while read_far_byte(segment, offset) != 0 {
+        let matches = name.iter().enumerate()
+            .all(|(i, &b)| read_far_byte(segment, offset + i as u16) == b);
+        let mut end = offset + name.len() as u16;
+        if matches && read_far_byte(segment, end) == b'=' {
+            let mut length = 0;
+            end += 1;
+            while length < buffer.len() {
+                let byte = read_far_byte(segment, end);
+                if byte == 0 {
+                    break;
+                }
+                buffer[length] = byte;
+                length += 1;
+                end += 1;
+            }
+            return Some(length);
+        }
+        while read_far_byte(segment, offset) != 0 {
+            offset += 1;
+        }
+        offset += 1;
+    }
+    None
+}
+
+/// Reads a byte from outside of the program's own segment.
+///
+/// # Arguments
+///
+/// * `segment` - The segment to read from
+/// * `offset` - The offset within the segment
+pub fn read_far_byte(segment: u16, offset: u16) -> u8 {
+    FarPtr::new(segment, offset).read()
+}
+
+/// Writes a byte outside of the program's own segment.
+///
+/// # Arguments
+///
+/// * `segment` - The segment to write to
+/// * `offset` - The offset within the segment
+/// * `value` - The byte to write
+#[allow(dead_code)]
+pub fn write_far_byte(segment: u16, offset: u16, value: u8) {
+    FarPtr::new(segment, offset).write(value);
+}
+
+/// Reads a 16-bit word from outside of the program's own segment.
+///
+/// # Arguments
+///
+/// * `segment` - The segment to read from
+/// * `offset` - The offset within the segment
+#[allow(dead_code)]
+pub fn read_far_word(segment: u16, offset: u16) -> u16 {
+    FarPtr::new(segment, offset).read()
+}
+
+/// Writes a 16-bit word outside of the program's own segment.
+///
+/// # Arguments
+///
+/// * `segment` - The segment to write to
+/// * `offset` - The offset within the segment
+/// * `value` - The word to write
+#[allow(dead_code)]
+pub fn write_far_word(segment: u16, offset: u16, value: u16) {
+    FarPtr::new(segment, offset).write(value);
 }
 
 /// Immediately shuts down the computer using APM.
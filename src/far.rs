@@ -0,0 +1,550 @@
+//! Far pointers into other segments
+//!
+//! The program's code, data and stack share one 64 KiB segment, which is all
+//! that its near pointers can reach. [`FarPtr`] addresses memory anywhere in
+//! the first megabyte as a segment and an offset, such as video memory, the
+//! BIOS data area or a block from [`crate::memory`], and [`FarSlice`] is a run
+//! of values that stays within one segment.
+//!
+//! DS is never changed, since the compiler assumes it points at the program.
+//! Instead, single values are read and written through a GS override, fills
+//! store through ES with `rep stosb`, and copies read through FS and write
+//! through ES with `rep movsb`. Each of them saves and restores the segment
+//! registers it uses: the compiler's own string instructions assume that ES
+//! matches DS.
+//!
+//! Bulk reads, writes, copies and fills that run past the end of a segment
+//! carry on into the memory after it, so they can cover more than 64 KiB.
+//! Only [`FarPtr::add`] wraps around within the segment.
+//!
+//! Like the rest of the program's hardware access, these are safe to call and
+//! nothing checks what the memory holds. It is up to the caller not to
+//! overwrite DOS, other programs or the program itself by accident.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::Range;
+use crate::dos;
+
+/// The most bytes that a single `rep` instruction is given when a range runs
+/// past the end of a segment.
+const CHUNK: u32 = 0x8000;
+
+/// A real mode segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment(u16);
+
+impl Segment {
+    /// The interrupt vector table.
+    pub const INTERRUPT_VECTORS: Segment = Segment(0x0000);
+    /// The IBM BIOS data area.
+    pub const BIOS_DATA: Segment = Segment(0x0040);
+
+    pub const fn new(value: u16) -> Self {
+        Segment(value)
+    }
+
+    /// The program's own segment, which near pointers are relative to.
+    pub fn program() -> Self {
+        Segment(dos::code_segment())
+    }
+
+    pub const fn value(self) -> u16 {
+        self.0
+    }
+
+    /// The linear address that the segment starts at.
+    pub const fn base(self) -> u32 {
+        (self.0 as u32) << 4
+    }
+
+    /// Returns a pointer to a value at an offset within the segment.
+    pub const fn ptr<T>(self, offset: u16) -> FarPtr<T> {
+        FarPtr::new(self.0, offset)
+    }
+
+    /// Returns a view of `len` values starting at an offset within the
+    /// segment.
+    ///
+    /// # Panics
+    ///
+    /// If the values would run past the end of the segment.
+    pub fn slice<T>(self, offset: u16, len: usize) -> FarSlice<T> {
+        FarSlice::new(self.ptr(offset), len)
+    }
+}
+
+/// A segment:offset pointer to a `T`, laid out like the CPU's far pointers.
+#[repr(C)]
+pub struct FarPtr<T> {
+    offset: u16,
+    segment: u16,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> Clone for FarPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FarPtr<T> {}
+
+impl<T> PartialEq for FarPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.segment == other.segment && self.offset == other.offset
+    }
+}
+
+impl<T> Eq for FarPtr<T> {}
+
+impl<T> core::fmt::Debug for FarPtr<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:04X}:{:04X}", self.segment, self.offset)
+    }
+}
+
+impl<T> FarPtr<T> {
+    pub const fn new(segment: u16, offset: u16) -> Self {
+        FarPtr { offset, segment, _marker: PhantomData }
+    }
+
+    /// Converts a near pointer into the program's segment.
+    pub fn from_near(pointer: *const T) -> Self {
+        FarPtr::new(dos::code_segment(), pointer as usize as u16)
+    }
+
+    /// Converts a linear address into a normalized pointer.
+    ///
+    /// Addresses from FFFF0h on, including the high memory area, can only be
+    /// reached through segment FFFFh, so their offsets go up to FFFFh.
+    ///
+    /// # Panics
+    ///
+    /// If the address is beyond what real mode can reach.
+    pub fn from_linear(address: u32) -> Self {
+        assert!(address < 0x10_FFF0);
+        if address >= 0xF_FFF0 {
+            FarPtr::new(0xFFFF, (address - 0xF_FFF0) as u16)
+        } else {
+            FarPtr::new((address >> 4) as u16, (address & 0xF) as u16)
+        }
+    }
+
+    pub const fn segment(self) -> Segment {
+        Segment(self.segment)
+    }
+
+    pub const fn offset(self) -> u16 {
+        self.offset
+    }
+
+    /// The linear address that the pointer refers to.
+    pub const fn linear(self) -> u32 {
+        ((self.segment as u32) << 4) + self.offset as u32
+    }
+
+    /// Returns the same address with the offset below 16, leaving as much of
+    /// the segment as possible after it, unless it is in the high memory area.
+    pub fn normalized(self) -> Self {
+        FarPtr::from_linear(self.linear())
+    }
+
+    /// Moves the pointer forward by `count` values.
+    ///
+    /// As with the CPU's own addressing, the offset wraps around within the
+    /// segment. Use [`FarPtr::normalized`] first to move further.
+    pub fn add(self, count: usize) -> Self {
+        let bytes = count.wrapping_mul(mem::size_of::<T>()) as u16;
+        FarPtr::new(self.segment, self.offset.wrapping_add(bytes))
+    }
+
+    pub const fn cast<U>(self) -> FarPtr<U> {
+        FarPtr::new(self.segment, self.offset)
+    }
+}
+
+impl<T: Copy> FarPtr<T> {
+    /// Reads the value.
+    pub fn read(self) -> T {
+        unsafe {
+            match mem::size_of::<T>() {
+                1 => mem::transmute_copy(&read_u8(self.segment, self.offset)),
+                2 => mem::transmute_copy(&read_u16(self.segment, self.offset)),
+                4 => mem::transmute_copy(&read_u32(self.segment, self.offset)),
+                size => {
+                    let mut value = MaybeUninit::<T>::uninit();
+                    let destination = FarPtr::<u8>::from_near(value.as_mut_ptr() as *const u8);
+                    copy_bytes(self.cast(), destination, size as u32);
+                    value.assume_init()
+                }
+            }
+        }
+    }
+
+    /// Writes the value.
+    pub fn write(self, value: T) {
+        unsafe {
+            match mem::size_of::<T>() {
+                1 => write_u8(self.segment, self.offset, mem::transmute_copy(&value)),
+                2 => write_u16(self.segment, self.offset, mem::transmute_copy(&value)),
+                4 => write_u32(self.segment, self.offset, mem::transmute_copy(&value)),
+                size => {
+                    let source = FarPtr::<u8>::from_near(&value as *const T as *const u8);
+                    copy_bytes(source, self.cast(), size as u32);
+                }
+            }
+        }
+    }
+
+    /// Reads consecutive values into a buffer, filling all of it.
+    pub fn read_into(self, buffer: &mut [T]) {
+        let destination = FarPtr::<u8>::from_near(buffer.as_ptr() as *const u8);
+        copy_bytes(self.cast(), destination, mem::size_of_val(buffer) as u32);
+    }
+
+    /// Writes a slice of values to consecutive addresses.
+    pub fn write_from(self, values: &[T]) {
+        let source = FarPtr::<u8>::from_near(values.as_ptr() as *const u8);
+        copy_bytes(source, self.cast(), mem::size_of_val(values) as u32);
+    }
+
+    /// Copies `count` values to another far address.
+    ///
+    /// The copy runs forwards, so overlapping ranges are only copied correctly
+    /// if the destination comes first.
+    pub fn copy_to(self, destination: FarPtr<T>, count: usize) {
+        copy_bytes(self.cast(), destination.cast(), (count * mem::size_of::<T>()) as u32);
+    }
+
+    /// Writes `value` to `count` consecutive addresses.
+    pub fn fill(self, value: T, count: usize) {
+        let size = mem::size_of::<T>();
+        if count == 0 {
+            return;
+        }
+        if size == 1 {
+            let byte: u8 = unsafe { mem::transmute_copy(&value) };
+            fill_bytes(self.cast(), byte, count as u32);
+        } else {
+            // Copying forwards from the first value onto the second repeats
+            // it all the way along.
+            self.write(value);
+            let second = FarPtr::from_linear(self.linear() + size as u32);
+            copy_bytes(self.cast(), second, ((count - 1) * size) as u32);
+        }
+    }
+}
+
+/// A run of values within one segment, like a slice in far memory.
+pub struct FarSlice<T> {
+    start: FarPtr<T>,
+    len: usize,
+}
+
+impl<T> Clone for FarSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for FarSlice<T> {}
+
+impl<T> FarSlice<T> {
+    /// # Panics
+    ///
+    /// If the values would run past the end of the segment.
+    pub fn new(start: FarPtr<T>, len: usize) -> Self {
+        assert!(start.offset as u32 + (len * mem::size_of::<T>()) as u32 <= 0x10000);
+        FarSlice { start, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_ptr(&self) -> FarPtr<T> {
+        self.start
+    }
+
+    /// Returns a pointer to a value, or `None` if the index is out of bounds.
+    pub fn get_ptr(&self, index: usize) -> Option<FarPtr<T>> {
+        if index < self.len { Some(self.start.add(index)) } else { None }
+    }
+
+    /// Returns a view of part of the slice.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds.
+    pub fn subslice(&self, range: Range<usize>) -> FarSlice<T> {
+        assert!(range.start <= range.end && range.end <= self.len);
+        FarSlice { start: self.start.add(range.start), len: range.end - range.start }
+    }
+}
+
+impl<T: Copy> FarSlice<T> {
+    pub fn get(&self, index: usize) -> Option<T> {
+        self.get_ptr(index).map(FarPtr::read)
+    }
+
+    /// # Panics
+    ///
+    /// If the index is out of bounds.
+    pub fn set(&self, index: usize, value: T) {
+        self.get_ptr(index).expect("index out of bounds").write(value);
+    }
+
+    /// Copies the whole slice into a buffer of the same length.
+    ///
+    /// # Panics
+    ///
+    /// If the lengths differ.
+    pub fn read_into(&self, buffer: &mut [T]) {
+        assert_eq!(buffer.len(), self.len);
+        self.start.read_into(buffer);
+    }
+
+    /// Overwrites the whole slice from a slice of the same length.
+    ///
+    /// # Panics
+    ///
+    /// If the lengths differ.
+    pub fn write_from(&self, values: &[T]) {
+        assert_eq!(values.len(), self.len);
+        self.start.write_from(values);
+    }
+
+    /// Overwrites the whole slice from another far slice of the same length.
+    ///
+    /// As with [`FarPtr::copy_to`], overlapping slices are only copied
+    /// correctly if this one comes first.
+    ///
+    /// # Panics
+    ///
+    /// If the lengths differ.
+    pub fn copy_from(&self, source: &FarSlice<T>) {
+        assert_eq!(source.len, self.len);
+        source.start.copy_to(self.start, self.len);
+    }
+
+    /// Sets every value in the slice.
+    pub fn fill(&self, value: T) {
+        self.start.fill(value, self.len);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(move |index| self.start.add(index).read())
+    }
+}
+
+/// Returns whether `length` bytes from a pointer stay within its segment.
+fn fits_in_segment(pointer: FarPtr<u8>, length: u32) -> bool {
+    length < 0x10000 && pointer.offset as u32 + length <= 0x10000
+}
+
+/// Returns how many of `remaining` bytes to handle in one go from a pointer,
+/// stopping at the end of its segment.
+fn chunk_length(pointer: FarPtr<u8>, remaining: u32) -> u32 {
+    remaining.min(CHUNK).min(0x10000 - pointer.offset as u32)
+}
+
+/// Copies bytes forwards from one far address to another. Ranges that leave
+/// their segment are copied a chunk at a time through normalized pointers.
+fn copy_bytes(source: FarPtr<u8>, destination: FarPtr<u8>, length: u32) {
+    if fits_in_segment(source, length) && fits_in_segment(destination, length) {
+        unsafe { copy_chunk(source, destination, length as u16) };
+        return;
+    }
+    let (mut source, mut destination) = (source.linear(), destination.linear());
+    let mut remaining = length;
+    while remaining > 0 {
+        let (from, to) = (FarPtr::from_linear(source), FarPtr::from_linear(destination));
+        let chunk = chunk_length(from, remaining).min(chunk_length(to, remaining));
+        unsafe { copy_chunk(from, to, chunk as u16) };
+        source += chunk;
+        destination += chunk;
+        remaining -= chunk;
+    }
+}
+
+/// Sets bytes starting at a far address, a chunk at a time like
+/// [`copy_bytes`].
+fn fill_bytes(destination: FarPtr<u8>, value: u8, length: u32) {
+    if fits_in_segment(destination, length) {
+        unsafe { fill_chunk(destination, value, length as u16) };
+        return;
+    }
+    let mut destination = destination.linear();
+    let mut remaining = length;
+    while remaining > 0 {
+        let to = FarPtr::from_linear(destination);
+        let chunk = chunk_length(to, remaining);
+        unsafe { fill_chunk(to, value, chunk as u16) };
+        destination += chunk;
+        remaining -= chunk;
+    }
+}
+
+unsafe fn copy_chunk(source: FarPtr<u8>, destination: FarPtr<u8>, length: u16) {
+    // SI can't be given to the compiler as an operand, so it is saved and
+    // loaded by hand.
+    asm!(
+        "push es",
+        "push fs",
+        "push esi",
+        "mov fs, {source_segment:x}",
+        "mov es, dx",
+        "mov si, ax",
+        "rep movsb byte ptr es:[di], byte ptr fs:[si]",
+        "pop esi",
+        "pop fs",
+        "pop es",
+        source_segment = in(reg) source.segment,
+        in("ax") source.offset,
+        in("dx") destination.segment,
+        inout("di") destination.offset => _,
+        inout("cx") length => _,
+    );
+}
+
+unsafe fn fill_chunk(destination: FarPtr<u8>, value: u8, length: u16) {
+    asm!(
+        "push es",
+        "mov es, dx",
+        "rep stosb",
+        "pop es",
+        in("dx") destination.segment,
+        inout("di") destination.offset => _,
+        inout("cx") length => _,
+        in("al") value,
+    );
+}
+
+unsafe fn read_u8(segment: u16, offset: u16) -> u8 {
+    let value;
+    asm!(
+        "push gs",
+        "mov gs, cx",
+        "mov al, byte ptr gs:[di]",
+        "pop gs",
+        in("cx") segment,
+        in("di") offset,
+        out("al") value,
+    );
+    value
+}
+
+unsafe fn read_u16(segment: u16, offset: u16) -> u16 {
+    let value;
+    asm!(
+        "push gs",
+        "mov gs, cx",
+        "mov ax, word ptr gs:[di]",
+        "pop gs",
+        in("cx") segment,
+        in("di") offset,
+        out("ax") value,
+    );
+    value
+}
+
+unsafe fn read_u32(segment: u16, offset: u16) -> u32 {
+    let value;
+    asm!(
+        "push gs",
+        "mov gs, cx",
+        "mov eax, dword ptr gs:[di]",
+        "pop gs",
+        in("cx") segment,
+        in("di") offset,
+        out("eax") value,
+    );
+    value
+}
+
+unsafe fn write_u8(segment: u16, offset: u16, value: u8) {
+    asm!(
+        "push gs",
+        "mov gs, cx",
+        "mov byte ptr gs:[di], al",
+        "pop gs",
+        in("cx") segment,
+        in("di") offset,
+        in("al") value,
+    );
+}
+
+unsafe fn write_u16(segment: u16, offset: u16, value: u16) {
+    asm!(
+        "push gs",
+        "mov gs, cx",
+        "mov word ptr gs:[di], ax",
+        "pop gs",
+        in("cx") segment,
+        in("di") offset,
+        in("ax") value,
+    );
+}
+
+unsafe fn write_u32(segment: u16, offset: u16, value: u32) {
+    asm!(
+        "push gs",
+        "mov gs, cx",
+        "mov dword ptr gs:[di], eax",
+        "pop gs",
+        in("cx") segment,
+        in("di") offset,
+        in("eax") value,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_linear() {
+        assert_eq!(FarPtr::<u8>::from_linear(0x12345), FarPtr::new(0x1234, 0x0005));
+        assert_eq!(FarPtr::<u8>::from_linear(0xF_FFEF), FarPtr::new(0xFFFE, 0x000F));
+        assert_eq!(FarPtr::<u8>::from_linear(0xF_FFF0), FarPtr::new(0xFFFF, 0x0000));
+        assert_eq!(FarPtr::<u8>::from_linear(0x10_0000), FarPtr::new(0xFFFF, 0x0010));
+        assert_eq!(FarPtr::<u8>::from_linear(0x10_FFEF), FarPtr::new(0xFFFF, 0xFFFF));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_linear_beyond_high_memory() {
+        FarPtr::<u8>::from_linear(0x10_FFF0);
+    }
+
+    #[test]
+    fn test_normalized() {
+        let pointer = FarPtr::<u8>::new(0x1234, 0x0105);
+        assert_eq!(pointer.normalized(), FarPtr::new(0x1244, 0x0005));
+        assert_eq!(pointer.normalized().linear(), pointer.linear());
+        assert_eq!(FarPtr::<u8>::new(0xF000, 0xFFFF).normalized(), FarPtr::new(0xFFFF, 0x000F));
+    }
+
+    #[test]
+    fn test_fits_in_segment() {
+        assert!(fits_in_segment(FarPtr::new(0x1000, 0xFFF0), 0x10));
+        assert!(!fits_in_segment(FarPtr::new(0x1000, 0xFFF0), 0x11));
+        assert!(fits_in_segment(FarPtr::new(0x1000, 0x0000), 0xFFFF));
+        assert!(!fits_in_segment(FarPtr::new(0x1000, 0x0000), 0x10000));
+    }
+
+    #[test]
+    fn test_chunk_length() {
+        assert_eq!(chunk_length(FarPtr::new(0x1000, 0x0005), 0x20000), CHUNK);
+        assert_eq!(chunk_length(FarPtr::new(0xFFFF, 0xFFF0), 0x20000), 0x10);
+        assert_eq!(chunk_length(FarPtr::new(0x1000, 0x0005), 3), 3);
+    }
+}
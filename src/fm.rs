@@ -0,0 +1,113 @@
+//! Chip-independent FM instrument definitions
+//!
+//! Both the AdLib's OPL2 and the PC-98's OPN are FM synthesizers, but they
+//! lay out their registers differently and support different operator counts.
+//! Music code describes a voice once as an [`Instrument`] and hands it to
+//! whichever [`FmChip`] is present; each driver translates it into its own
+//! register writes.
+//!
+//! Instruments are two-operator voices using OPL2 value ranges (4-bit rates,
+//! 6-bit total level). The OPN driver scales these to its wider ranges and
+//! leaves its third and fourth operators keyed off.
+
+#![allow(dead_code)]
+
+/// The parameters of a single FM operator.
+#[derive(Clone, Copy)]
+pub struct Operator {
+    /// Attack rate (0-15, higher is faster).
+    pub attack: u8,
+    /// Decay rate (0-15, higher is faster).
+    pub decay: u8,
+    /// Sustain level (0-15, higher is quieter).
+    pub sustain_level: u8,
+    /// Release rate (0-15, higher is faster).
+    pub release: u8,
+    /// Frequency multiplier (0-15, where 0 means one half).
+    pub multiple: u8,
+    /// Attenuation (0-63, higher is quieter).
+    pub total_level: u8,
+    /// Attenuation that increases with pitch (0-3). Ignored by the OPN.
+    pub key_scale_level: u8,
+    /// Waveform select (0-3). Ignored by the OPN, which only has sine waves.
+    pub waveform: u8,
+    /// Whether the envelope holds at the sustain level until key off.
+    pub sustained: bool,
+}
+
+/// A two-operator FM voice.
+#[derive(Clone, Copy)]
+pub struct Instrument {
+    pub modulator: Operator,
+    pub carrier: Operator,
+    /// Modulator self-feedback (0-7).
+    pub feedback: u8,
+    /// Output both operators directly instead of having the modulator
+    /// modulate the carrier.
+    pub additive: bool,
+}
+
+impl Instrument {
+    /// A simple electric piano, useful as a default voice.
+    pub const PIANO: Instrument = Instrument {
+        modulator: Operator {
+            attack: 15,
+            decay: 4,
+            sustain_level: 5,
+            release: 5,
+            multiple: 1,
+            total_level: 22,
+            key_scale_level: 1,
+            waveform: 0,
+            sustained: false,
+        },
+        carrier: Operator {
+            attack: 13,
+            decay: 2,
+            sustain_level: 4,
+            release: 4,
+            multiple: 1,
+            total_level: 0,
+            key_scale_level: 0,
+            waveform: 0,
+            sustained: false,
+        },
+        feedback: 3,
+        additive: false,
+    };
+}
+
+/// An FM synthesizer that can play [`Instrument`]s.
+///
+/// Notes are given as MIDI note numbers, where 60 is middle C.
+pub trait FmChip {
+    /// The number of melodic channels the chip provides.
+    const CHANNELS: u8;
+
+    /// Loads an instrument into a channel.
+    fn set_instrument(&mut self, channel: u8, instrument: &Instrument);
+
+    /// Starts playing a note on a channel.
+    fn note_on(&mut self, channel: u8, note: u8);
+
+    /// Releases the note playing on a channel.
+    fn note_off(&mut self, channel: u8);
+
+    /// Releases the notes on every channel.
+    fn silence(&mut self) {
+        for channel in 0..Self::CHANNELS {
+            self.note_off(channel);
+        }
+    }
+}
+
+/// Splits a MIDI note into an octave block and a semitone within it.
+///
+/// `offset` is added to the MIDI octave (where note 60 is in octave 5) so that
+/// each chip can line the result up with its own frequency table. The block is
+/// clamped to the 3-bit range both chips use.
+pub fn split_note(note: u8, offset: i8) -> (u8, usize) {
+    let block = (note / 12) as i8 + offset;
+    let block = if block < 0 { 0 } else if block > 7 { 7 } else { block as u8 };
+    (block, (note % 12) as usize)
+}
@@ -0,0 +1,160 @@
+//! Platform-independent access to the basic hardware
+//!
+//! Each function picks the backend for the platform found by
+//! [`platform::current`]. Backends for platforms whose cargo feature is
+//! disabled are left out of the binary, and `current` never returns those
+//! platforms, so the fallback arms are never taken.
+
+#![allow(dead_code)]
+
+use crate::platform::{self, Platform};
+use crate::dos;
+#[cfg(feature = "ibm")]
+use crate::speaker;
+#[cfg(feature = "ibm")]
+use crate::video;
+#[cfg(feature = "pc98")]
+use crate::pc98;
+
+/// A key press, as the BIOS reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    /// The IBM scan code of the physical key, as INT 16h reports it. PC-98
+    /// key codes are translated, and keys with no IBM equivalent are 0.
+    pub scan_code: u8,
+    /// The character typed, or 0 for keys that don't type one.
+    pub character: u8,
+}
+
+impl Key {
+    fn from_bios(key: u16) -> Self {
+        Key { scan_code: (key >> 8) as u8, character: key as u8 }
+    }
+}
+
+/// Returns the next key press without waiting, or `None` if there isn't one.
+pub fn read_key() -> Option<Key> {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => dos::get_key().map(Key::from_bios),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => pc98::keyboard::read_key(),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+/// Starts a beep at roughly the given frequency.
+pub fn beep(frequency: u16) {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => speaker::tone(frequency),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => pc98::beeper::tone(frequency),
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+}
+
+/// Stops the beep started by [`beep`].
+pub fn stop_beep() {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => speaker::silence(),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => pc98::beeper::silence(),
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+}
+
+/// Switches to an 80 column text display and clears it.
+pub fn text_mode() {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => dos::set_video_mode(0x03),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => {
+            pc98::graphics::shutdown();
+            pc98::show_text();
+            pc98::clear_text();
+        }
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+}
+
+/// Switches to the platform's graphics mode: 320x200 with 256 colors on IBM
+/// compatibles, or 640x400 with 16 colors on the PC-98.
+pub fn graphics_mode() {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => dos::set_video_mode(0x13),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => pc98::graphics::init(),
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+}
+
+/// Returns the width and height in pixels of the mode [`graphics_mode`]
+/// switches to.
+pub fn screen_size() -> (u16, u16) {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => (320, 200),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => (pc98::graphics::WIDTH, pc98::graphics::HEIGHT),
+        #[allow(unreachable_patterns)]
+        _ => (0, 0),
+    }
+}
+
+/// Fills the screen with a palette index in graphics mode.
+pub fn fill_screen(color: u8) {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => video::fill_screen(color),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => pc98::graphics::fill_screen(color),
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+}
+
+/// Draws a rectangular box outline in graphics mode.
+pub fn draw_box(x: u16, y: u16, w: u16, h: u16, color: u8) {
+    match platform::current() {
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => video::draw_box(x, y, w, h, color),
+        #[cfg(feature = "pc98")]
+        Platform::Pc98 => pc98::graphics::draw_box(x, y, w, h, color),
+        #[allow(unreachable_patterns)]
+        _ => {}
+    }
+}
+
+/// Returns a clock in milliseconds, for measuring how much time has passed.
+///
+/// The starting point is unspecified, and it wraps around daily. It only
+/// advances as often as the platform's clock ticks, which on IBM compatibles
+/// is about every 55 ms.
+pub fn milliseconds() -> u32 {
+    match platform::current() {
+        // The BIOS counts 18.2 Hz timer ticks since midnight at 0040:006C.
+        #[cfg(feature = "ibm")]
+        Platform::Ibm => {
+            let ticks = (0..4).rev().fold(0u32, |ticks, i| {
+                ticks << 8 | dos::read_far_byte(0x0040, 0x006C + i) as u32
+            });
+            ticks * 55
+        }
+        // The PC-98 BIOS doesn't count ticks, but DOS keeps the time of day.
+        #[allow(unreachable_patterns)]
+        _ => {
+            let (hours, minutes, seconds, hundredths) = dos::get_time();
+            let seconds = (hours as u32 * 60 + minutes as u32) * 60 + seconds as u32;
+            seconds * 1000 + hundredths as u32 * 10
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Line input from the keyboard
+//!
+//! [`read_line_dos`] uses DOS's buffered input function, which gives the
+//! familiar DOS editing keys and reads from a file when input is redirected.
+//! [`LineEditor`] reads keys itself to offer cursor movement, insert and
+//! overwrite modes, and recalling earlier lines with the up and down arrows.
+//!
+//! Both return the line as UTF-8, decoded from the active code page.
+//! [`read_line`] uses a shared editor, so that the history covers every line
+//! the program reads. The editor echoes to the console device rather than to
+//! standard output, so typing doesn't end up in redirected output, and
+//! [`read_line`] reads from standard input instead when it is redirected.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::ptr::addr_of_mut;
+use crate::dos;
+use crate::hal::{self, Key};
+use crate::io;
+use crate::text;
+
+/// The longest line, in bytes of the code page, that can be read.
+pub const MAX_LINE: usize = 127;
+
+/// The number of earlier lines that a [`LineEditor`] remembers.
+pub const HISTORY_SIZE: usize = 8;
+
+// IBM scan codes, which the PC-98 keyboard backend translates to.
+const UP: u8 = 0x48;
+const DOWN: u8 = 0x50;
+const LEFT: u8 = 0x4B;
+const RIGHT: u8 = 0x4D;
+const HOME: u8 = 0x47;
+const END: u8 = 0x4F;
+const INSERT: u8 = 0x52;
+const DELETE: u8 = 0x53;
+
+const BACKSPACE: u8 = 0x08;
+const ENTER: u8 = b'\r';
+const ESCAPE: u8 = 0x1B;
+
+static mut EDITOR: LineEditor = LineEditor::new();
+
+/// Reads a line with the shared [`LineEditor`], or from standard input
+/// without editing if it isn't the console.
+///
+/// # Arguments
+///
+/// * `output` - Where to put the line as UTF-8. Characters that don't fit are
+///   left out.
+///
+/// # Returns
+///
+/// The line, without its line ending
+pub fn read_line(output: &mut [u8]) -> &str {
+    if !io::is_console(io::STDIN) {
+        return read_line_redirected(output);
+    }
+    unsafe { (*addr_of_mut!(EDITOR)).read_line(output) }
+}
+
+/// Reads a line from standard input through [`io::stdin`], dropping bytes
+/// past [`MAX_LINE`].
+fn read_line_redirected(output: &mut [u8]) -> &str {
+    let mut bytes = [0u8; MAX_LINE];
+    let mut length = 0;
+    let stdin = io::stdin();
+    while let Some(byte) = stdin.read_byte() {
+        match byte {
+            b'\n' => break,
+            b'\r' => {}
+            _ if length < MAX_LINE => {
+                bytes[length] = byte;
+                length += 1;
+            }
+            _ => {}
+        }
+    }
+    text::decode_into(text::active(), &bytes[..length], output)
+}
+
+/// Reads a line using DOS interrupt 21h function 0Ah.
+///
+/// # Arguments
+///
+/// * `output` - Where to put the line as UTF-8. Characters that don't fit are
+///   left out.
+///
+/// # Returns
+///
+/// The line, without its carriage return
+pub fn read_line_dos(output: &mut [u8]) -> &str {
+    // The first byte is the buffer size, and DOS fills in the length read and
+    // the characters after it, ending with a carriage return.
+    let mut buffer = [0u8; MAX_LINE + 3];
+    buffer[0] = MAX_LINE as u8 + 1;
+    io::flush();
+    unsafe {
+        asm!(
+            "int 21h",
+            inout("ax") 0x0A00u16 => _,
+            in("dx") buffer.as_mut_ptr(),
+        );
+    }
+    // DOS only echoes the carriage return.
+    dos::print_character(b'\n');
+    let length = buffer[1] as usize;
+    text::decode_into(text::active(), &buffer[2..2 + length], output)
+}
+
+/// A line editor that remembers the lines it has read.
+pub struct LineEditor {
+    history: [[u8; MAX_LINE]; HISTORY_SIZE],
+    history_lengths: [u8; HISTORY_SIZE],
+    /// The number of lines in the history.
+    history_count: usize,
+    /// Where the next line goes in the history, which is a ring buffer.
+    history_next: usize,
+    /// Whether typing inserts rather than overwrites. This carries over from
+    /// one line to the next.
+    insert: bool,
+}
+
+/// The line being edited, with the screen cursor kept at `cursor`.
+struct Line {
+    bytes: [u8; MAX_LINE],
+    length: usize,
+    cursor: usize,
+}
+
+impl Line {
+    /// Writes bytes to the screen, moving the cursor past them.
+    fn echo(bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| dos::console_character(byte));
+    }
+
+    /// Moves the screen cursor back over `count` characters.
+    fn back(count: usize) {
+        (0..count).for_each(|_| dos::console_character(BACKSPACE));
+    }
+
+    /// Redraws from the cursor to the end of the line, blanking out
+    /// `erased` characters that used to be past the end.
+    fn redraw_tail(&self, erased: usize) {
+        Line::echo(&self.bytes[self.cursor..self.length]);
+        (0..erased).for_each(|_| dos::console_character(b' '));
+        Line::back(self.length - self.cursor + erased);
+    }
+
+    fn type_byte(&mut self, byte: u8, insert: bool) {
+        if insert || self.cursor == self.length {
+            if self.length == MAX_LINE {
+                return;
+            }
+            self.bytes.copy_within(self.cursor..self.length, self.cursor + 1);
+            self.length += 1;
+        }
+        self.bytes[self.cursor] = byte;
+        self.cursor += 1;
+        Line::echo(&[byte]);
+        if insert {
+            self.redraw_tail(0);
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    fn delete(&mut self) {
+        if self.cursor < self.length {
+            self.bytes.copy_within(self.cursor + 1..self.length, self.cursor);
+            self.length -= 1;
+            self.redraw_tail(1);
+        }
+    }
+
+    fn move_to(&mut self, position: usize) {
+        if position < self.cursor {
+            Line::back(self.cursor - position);
+        } else {
+            Line::echo(&self.bytes[self.cursor..position]);
+        }
+        self.cursor = position;
+    }
+
+    /// Replaces the whole line, leaving the cursor at the end.
+    fn replace(&mut self, bytes: &[u8]) {
+        self.move_to(0);
+        let old_length = self.length;
+        self.bytes[..bytes.len()].copy_from_slice(bytes);
+        self.length = bytes.len();
+        Line::echo(bytes);
+        let erased = old_length.saturating_sub(self.length);
+        (0..erased).for_each(|_| dos::console_character(b' '));
+        Line::back(erased);
+        self.cursor = self.length;
+    }
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        LineEditor {
+            history: [[0; MAX_LINE]; HISTORY_SIZE],
+            history_lengths: [0; HISTORY_SIZE],
+            history_count: 0,
+            history_next: 0,
+            insert: true,
+        }
+    }
+
+    /// Reads a line, echoing it from the current cursor position.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Where to put the line as UTF-8. Characters that don't fit
+    ///   are left out.
+    ///
+    /// # Returns
+    ///
+    /// The line, without its line ending
+    pub fn read_line<'a>(&mut self, output: &'a mut [u8]) -> &'a str {
+        io::flush();
+        let mut line = Line { bytes: [0; MAX_LINE], length: 0, cursor: 0 };
+        // How many lines back in the history the line was recalled from, with
+        // 0 meaning a new line.
+        let mut recalled = 0;
+        loop {
+            let key = wait_for_key();
+            // IBM extended keys have a character of 0, or E0h on enhanced
+            // keyboards, while the PC-98 gives its cursor keys control
+            // characters. Either way, look at the scan code first.
+            let control = key.character < 0x20 || key.character == 0x7F
+                || (key.character == 0xE0 && key.scan_code != 0);
+            if !control {
+                line.type_byte(key.character, self.insert);
+                continue;
+            }
+            match (key.scan_code, key.character) {
+                (LEFT, _) if line.cursor > 0 => line.move_to(line.cursor - 1),
+                (RIGHT, _) if line.cursor < line.length => line.move_to(line.cursor + 1),
+                (HOME, _) => line.move_to(0),
+                (END, _) => line.move_to(line.length),
+                (DELETE, _) => line.delete(),
+                (INSERT, _) => self.insert = !self.insert,
+                (UP, _) if recalled < self.history_count => {
+                    recalled += 1;
+                    line.replace(self.history_entry(recalled));
+                }
+                (DOWN, _) if recalled > 0 => {
+                    recalled -= 1;
+                    line.replace(if recalled == 0 { &[] } else { self.history_entry(recalled) });
+                }
+                (LEFT, _) | (RIGHT, _) | (UP, _) | (DOWN, _) => {}
+                (_, ENTER) => break,
+                (_, BACKSPACE) if line.cursor > 0 => {
+                    line.move_to(line.cursor - 1);
+                    line.delete();
+                }
+                (_, ESCAPE) => line.replace(&[]),
+                _ => {}
+            }
+        }
+        Line::echo(b"\r\n");
+        self.remember(&line.bytes[..line.length]);
+        text::decode_into(text::active(), &line.bytes[..line.length], output)
+    }
+
+    /// Returns the line `back` entries before the next one, starting from 1.
+    fn history_entry(&self, back: usize) -> &[u8] {
+        let index = (self.history_next + HISTORY_SIZE - back) % HISTORY_SIZE;
+        &self.history[index][..self.history_lengths[index] as usize]
+    }
+
+    /// Adds a line to the history, unless it is empty or the same as the most
+    /// recent one.
+    fn remember(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() || (self.history_count > 0 && self.history_entry(1) == bytes) {
+            return;
+        }
+        self.history[self.history_next][..bytes.len()].copy_from_slice(bytes);
+        self.history_lengths[self.history_next] = bytes.len() as u8;
+        self.history_next = (self.history_next + 1) % HISTORY_SIZE;
+        self.history_count = (self.history_count + 1).min(HISTORY_SIZE);
+    }
+}
+
+fn wait_for_key() -> Key {
+    loop {
+        if let Some(key) = hal::read_key() {
+            return key;
+        }
+    }
+}
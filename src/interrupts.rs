@@ -0,0 +1,342 @@
+//! Interrupt vector hooking and Rust interrupt handlers
+//!
+//! [`hook`] points an interrupt vector at a handler and returns a
+//! [`VectorGuard`] that puts the previous handler back when dropped. Every
+//! hook is also recorded in a table so that [`restore_all`] can undo them on
+//! paths that leave the program without unwinding, such as panics. Returning
+//! to DOS with a vector still pointing into the program would crash the
+//! machine as soon as its memory is reused.
+//!
+//! Handlers written in Rust are wrapped in a trampoline by the
+//! [`interrupt_handler!`] macro. The trampoline saves every register, points
+//! DS and ES at the program's segment, and switches to a private stack if the
+//! interrupt arrived while running on someone else's (such as DOS's or the
+//! BIOS's), since Rust code assumes that SS is the same as DS.
+//!
+//! Handlers run with interrupts disabled and must not enable them, as nested
+//! handlers would share the private stack. Hardware interrupt handlers that
+//! don't chain must acknowledge the interrupt themselves through [`crate::pic`].
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::ptr::{addr_of_mut, read_volatile};
+use crate::dos;
+
+/// The size of the stack that handlers run on.
+pub const STACK_SIZE: usize = 1024;
+
+/// The most vectors that can be hooked at once.
+pub const MAX_HOOKS: usize = 8;
+
+#[doc(hidden)]
+pub static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+/// Every active hook, in whichever slot was free when it was installed.
+static mut HOOKS: [Option<Hook>; MAX_HOOKS] = [None; MAX_HOOKS];
+
+/// The sequence number to give the next hook.
+static mut NEXT_SEQUENCE: u32 = 0;
+
+/// A hooked vector, the handler it replaced and the handler installed.
+#[derive(Clone, Copy)]
+struct Hook {
+    /// Counts up with every hook, so that hooks can be undone newest first
+    /// whatever slots they are in.
+    sequence: u32,
+    vector: u8,
+    previous: FarAddress,
+    handler: &'static Handler,
+}
+
+/// A real mode segment:offset address, laid out the way the CPU expects for
+/// indirect far jumps and calls.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FarAddress {
+    pub offset: u16,
+    pub segment: u16,
+}
+
+impl FarAddress {
+    pub const NULL: FarAddress = FarAddress { offset: 0, segment: 0 };
+}
+
+/// What a Rust interrupt handler wants to happen once it returns.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    /// Return from the interrupt. The handler must have sent any EOI needed.
+    Return = 0,
+    /// Jump to the handler that was installed before, which takes care of
+    /// returning from the interrupt.
+    Previous = 1,
+}
+
+/// An interrupt entry point and the slot that its trampoline reads the
+/// previous handler from when chaining.
+pub struct Handler {
+    entry: unsafe extern "C" fn(),
+    previous: *mut FarAddress,
+}
+
+// The pointer is only ever written while hooking, with interrupts disabled.
+unsafe impl Sync for Handler {}
+
+impl Handler {
+    /// Wraps a handler written in assembly.
+    ///
+    /// # Safety
+    ///
+    /// `entry` must end with `iret` or a jump through `previous`, and preserve
+    /// every register it uses.
+    pub const unsafe fn from_raw(entry: unsafe extern "C" fn(), previous: *mut FarAddress) -> Self {
+        Handler { entry, previous }
+    }
+}
+
+/// Defines a static [`Handler`] that runs a Rust function.
+///
+/// The function takes no arguments and returns a [`Chain`].
+///
+/// ```ignore
+/// fn on_tick() -> Chain {
+///     TICKS += 1;
+///     Chain::Previous
+/// }
+///
+/// interrupt_handler!(TICK_HANDLER => on_tick);
+/// let _guard = interrupts::hook(0x1C, &TICK_HANDLER);
+/// ```
+#[macro_export]
+macro_rules! interrupt_handler {
+    ($vis:vis $name:ident => $handler:path) => {
+        $vis static $name: $crate::interrupts::Handler = {
+            static mut PREVIOUS: $crate::interrupts::FarAddress = $crate::interrupts::FarAddress::NULL;
+
+            extern "C" fn run() -> u8 {
+                let handler: fn() -> $crate::interrupts::Chain = $handler;
+                handler() as u8
+            }
+
+            #[unsafe(naked)]
+            unsafe extern "C" fn entry() {
+                core::arch::naked_asm!(
+                    "pushad",
+                    "push ds",
+                    "push es",
+                    "push fs",
+                    "push gs",
+                    // A .COM program's data lives in its code segment.
+                    "mov ax, cs",
+                    "mov ds, ax",
+                    "mov es, ax",
+                    // Keep the current stack if it is already ours, so that
+                    // an interrupt during the program's own code doesn't
+                    // throw away its stack. Otherwise switch to the private
+                    // one.
+                    "mov dx, ss",
+                    "mov ecx, esp",
+                    "cmp dx, ax",
+                    "je 2f",
+                    "mov ss, ax",
+                    "mov esp, offset {stack}",
+                    "add esp, {stack_size}",
+                    "2:",
+                    "push edx",
+                    "push ecx",
+                    "cld",
+                    // Rust functions return with a 32-bit RET.
+                    "data32 call {call}",
+                    "pop ecx",
+                    "pop edx",
+                    "mov ss, dx",
+                    "mov esp, ecx",
+                    "pop gs",
+                    "pop fs",
+                    "pop es",
+                    "pop ds",
+                    // POPAD leaves the flags alone, so test the result first.
+                    "cmp al, 0",
+                    "popad",
+                    "jne 3f",
+                    "iret",
+                    "3:",
+                    "ljmp dword ptr cs:[{previous}]",
+                    stack = sym $crate::interrupts::STACK,
+                    stack_size = const $crate::interrupts::STACK_SIZE,
+                    call = sym run,
+                    previous = sym PREVIOUS,
+                );
+            }
+
+            unsafe { $crate::interrupts::Handler::from_raw(entry, core::ptr::addr_of_mut!(PREVIOUS)) }
+        };
+    };
+}
+
+/// Restores a hooked vector when dropped.
+pub struct VectorGuard {
+    slot: usize,
+}
+
+impl VectorGuard {
+    /// Returns the handler that was installed before the hook.
+    pub fn previous(&self) -> FarAddress {
+        unsafe {
+            match (*addr_of_mut!(HOOKS))[self.slot] {
+                Some(hook) => hook.previous,
+                None => FarAddress::NULL,
+            }
+        }
+    }
+}
+
+impl Drop for VectorGuard {
+    fn drop(&mut self) {
+        unsafe {
+            // The hook may already have been undone by `restore_all`.
+            if let Some(hook) = (*addr_of_mut!(HOOKS))[self.slot].take() {
+                set_vector(hook.vector, hook.previous);
+            }
+        }
+    }
+}
+
+/// Returns the handler an interrupt vector points to.
+pub fn get_vector(vector: u8) -> FarAddress {
+    let (segment, offset) = dos::get_interrupt_vector(vector);
+    FarAddress { offset, segment }
+}
+
+/// Points an interrupt vector at a handler.
+pub fn set_vector(vector: u8, handler: FarAddress) {
+    dos::set_interrupt_vector(vector, handler.segment, handler.offset);
+}
+
+/// Points an interrupt vector at a handler until the returned guard is
+/// dropped.
+///
+/// Returns `None` if [`MAX_HOOKS`] vectors are already hooked, or if the
+/// handler is already installed. A handler only has room for one previous
+/// handler, so installing it twice would make it chain to itself.
+pub fn hook(vector: u8, handler: &'static Handler) -> Option<VectorGuard> {
+    let hooks = unsafe { &*addr_of_mut!(HOOKS) };
+    if hooks.iter().flatten().any(|hook| core::ptr::eq(hook.handler, handler)) {
+        return None;
+    }
+    let slot = hooks.iter().position(Option::is_none)?;
+    let previous = get_vector(vector);
+    let entry = FarAddress {
+        offset: handler.entry as usize as u16,
+        segment: dos::code_segment(),
+    };
+    without_interrupts(|| unsafe {
+        let sequence = NEXT_SEQUENCE;
+        NEXT_SEQUENCE += 1;
+        *handler.previous = previous;
+        (*addr_of_mut!(HOOKS))[slot] = Some(Hook { sequence, vector, previous, handler });
+        set_vector(vector, entry);
+    });
+    Some(VectorGuard { slot })
+}
+
+/// Restores every hooked vector, most recent first.
+///
+/// Guards for these hooks do nothing when they are dropped afterwards.
+pub fn restore_all() {
+    unsafe { restore_hooks(&mut *addr_of_mut!(HOOKS), set_vector) }
+}
+
+/// Undoes hooks in the reverse of the order they were installed in, so that
+/// a vector hooked twice ends up back at its original handler.
+fn restore_hooks(hooks: &mut [Option<Hook>], mut set_vector: impl FnMut(u8, FarAddress)) {
+    while let Some(slot) = latest(hooks) {
+        if let Some(hook) = hooks[slot].take() {
+            set_vector(hook.vector, hook.previous);
+        }
+    }
+}
+
+/// Returns the slot of the most recently installed hook.
+fn latest(hooks: &[Option<Hook>]) -> Option<usize> {
+    hooks.iter()
+        .enumerate()
+        .filter_map(|(slot, hook)| hook.map(|hook| (hook.sequence, slot)))
+        .max()
+        .map(|(_, slot)| slot)
+}
+
+/// Returns whether any vectors are hooked.
+pub fn any_hooked() -> bool {
+    unsafe { read_volatile(addr_of_mut!(HOOKS)).iter().any(Option::is_some) }
+}
+
+/// Runs a closure with maskable interrupts disabled, then restores the
+/// interrupt flag to what it was before.
+pub fn without_interrupts<T, F: FnOnce() -> T>(f: F) -> T {
+    let flags: u16;
+    unsafe {
+        asm!(
+            "pushf",
+            "pop {0:x}",
+            "cli",
+            out(reg) flags,
+        );
+    }
+    let result = f();
+    if flags & 0x0200 != 0 {
+        unsafe { asm!("sti"); }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr;
+
+    unsafe extern "C" fn entry() {}
+
+    static A: Handler = unsafe { Handler::from_raw(entry, ptr::null_mut()) };
+    static B: Handler = unsafe { Handler::from_raw(entry, ptr::null_mut()) };
+    static C: Handler = unsafe { Handler::from_raw(entry, ptr::null_mut()) };
+    static D: Handler = unsafe { Handler::from_raw(entry, ptr::null_mut()) };
+
+    /// Hooks a vector of a simulated vector table the way [`hook`] does,
+    /// pointing it at an address whose offset is the hook's sequence number.
+    fn install(hooks: &mut [Option<Hook>], vectors: &mut [FarAddress], sequence: u32, vector: u8, handler: &'static Handler) {
+        let slot = hooks.iter().position(Option::is_none).unwrap();
+        hooks[slot] = Some(Hook { sequence, vector, previous: vectors[vector as usize], handler });
+        vectors[vector as usize] = FarAddress { offset: sequence as u16, segment: 0x1234 };
+    }
+
+    #[test]
+    fn test_restore_order() {
+        let mut hooks = [None; MAX_HOOKS];
+        let mut vectors = [FarAddress::NULL; 256];
+        for (vector, address) in vectors.iter_mut().enumerate() {
+            *address = FarAddress { offset: vector as u16, segment: 0xF000 };
+        }
+        let original = vectors;
+        install(&mut hooks, &mut vectors, 0, 0x09, &A);
+        install(&mut hooks, &mut vectors, 1, 0x1C, &B);
+        install(&mut hooks, &mut vectors, 2, 0x08, &C);
+        // Dropping A frees the first slot, which D then takes.
+        let a = hooks[0].take().unwrap();
+        vectors[a.vector as usize] = a.previous;
+        install(&mut hooks, &mut vectors, 3, 0x1C, &D);
+        assert!(ptr::eq(hooks[0].unwrap().handler, &D));
+
+        let mut order = [0u8; 3];
+        let mut restored = 0;
+        restore_hooks(&mut hooks, |vector, previous| {
+            order[restored] = vectors[vector as usize].offset as u8;
+            vectors[vector as usize] = previous;
+            restored += 1;
+        });
+        assert_eq!(order, [3, 2, 1]);
+        assert!(hooks.iter().all(Option::is_none));
+        assert!(vectors == original);
+    }
+}
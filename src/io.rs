@@ -1,25 +1,292 @@
+//! Buffered input and output on the standard handles
+//!
+//! Text goes to standard output (handle 1) or standard error (handle 2)
+//! through DOS's handle write function, so `program > out.txt` captures the
+//! output while error messages still reach the screen. Strings are encoded
+//! in the active code page, and line feeds become CR LF as DOS expects.
+//!
+//! Standard output is buffered and flushed at every line feed, when the
+//! buffer fills and by [`flush`], which [`exit`] and the panic handler call.
+//! Standard error is flushed after every write, after flushing standard
+//! output so that the two stay in order on the screen.
+//!
+//! Standard input (handle 0) is read with [`Reader`]. [`handle_kind`] tells
+//! whether a handle is the console or has been redirected, so that filters
+//! can leave out prompts and colors when their output goes to a file. DOS
+//! implements pipes with temporary files, so a pipe looks like a file.
+
+#![allow(dead_code)]
+
+use core::fmt::{self, Write};
+use core::ptr::addr_of_mut;
+use crate::a20;
 use crate::dos;
+use crate::interrupts;
+use crate::pit;
+use crate::text;
+
+pub const STDIN: u16 = 0;
+pub const STDOUT: u16 = 1;
+pub const STDERR: u16 = 2;
+
+const BUFFER_SIZE: usize = 128;
+
+/// A buffered writer to a DOS handle.
+pub struct Writer {
+    handle: u16,
+    buffer: [u8; BUFFER_SIZE],
+    length: usize,
+    /// The last byte written, to avoid doubling up carriage returns.
+    last_byte: u8,
+}
+
+impl Writer {
+    pub const fn new(handle: u16) -> Self {
+        Writer { handle, buffer: [0; BUFFER_SIZE], length: 0, last_byte: 0 }
+    }
+
+    /// Writes a byte as it is, flushing if it is a line feed.
+    pub fn write_byte(&mut self, byte: u8) {
+        if self.length == BUFFER_SIZE {
+            self.flush();
+        }
+        self.buffer[self.length] = byte;
+        self.length += 1;
+        self.last_byte = byte;
+        if byte == b'\n' {
+            self.flush();
+        }
+    }
+
+    /// Writes raw bytes without encoding them.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| self.write_byte(byte));
+    }
+
+    /// Writes the bytes in the buffer to the handle.
+    ///
+    /// Errors are ignored, as there is nowhere left to report them.
+    pub fn flush(&mut self) {
+        if self.length > 0 {
+            let _ = dos::write_handle(self.handle, &self.buffer[..self.length]);
+            self.length = 0;
+        }
+    }
+
+    /// Writes an encoded byte, putting a carriage return before line feeds.
+    fn write_text_byte(&mut self, byte: u8) {
+        if byte == b'\n' && self.last_byte != b'\r' {
+            self.write_byte(b'\r');
+        }
+        self.write_byte(byte);
+    }
+}
+
+impl Write for Writer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let codepage = text::active();
+        if text::transliteration() {
+            text::transliterate::transliterate(codepage, s).for_each(|byte| self.write_text_byte(byte));
+        } else {
+            s.chars().for_each(|c| self.write_text_byte(codepage.encode_char_lossy(c)));
+        }
+        Ok(())
+    }
+}
+
+/// What a handle refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandleKind {
+    /// The keyboard and screen.
+    Console,
+    /// Some other character device, such as `NUL`, `PRN` or `AUX`.
+    Device,
+    /// A file on disk, or a pipe.
+    File,
+}
+
+/// Finds out what a handle refers to, or returns `None` if it isn't open.
+pub fn handle_kind(handle: u16) -> Option<HandleKind> {
+    let information = dos::get_device_information(handle).ok()?;
+    Some(if information & 0x0080 == 0 {
+        HandleKind::File
+    // Bits 0 and 1 mark the console input and output devices.
+    } else if information & 0x0003 != 0 {
+        HandleKind::Console
+    } else {
+        HandleKind::Device
+    })
+}
+
+/// Returns whether a handle refers to the console rather than being
+/// redirected.
+pub fn is_console(handle: u16) -> bool {
+    handle_kind(handle) == Some(HandleKind::Console)
+}
 
-/// Writes raw bytes directly to the screen.
+/// A buffered reader from a DOS handle, which is also an iterator over its
+/// bytes.
+///
+/// Reading from the console waits for a whole line, which DOS lets the user
+/// edit.
+pub struct Reader {
+    handle: u16,
+    buffer: [u8; BUFFER_SIZE],
+    position: usize,
+    length: usize,
+    /// The DOS error code of the last failed read.
+    error: Option<u16>,
+}
+
+impl Reader {
+    pub const fn new(handle: u16) -> Self {
+        Reader { handle, buffer: [0; BUFFER_SIZE], position: 0, length: 0, error: None }
+    }
+
+    /// Reads bytes into `buffer`, returning how many were read. This is 0 at
+    /// the end of the input or after an error.
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        if self.position == self.length && !self.fill() {
+            return 0;
+        }
+        let count = buffer.len().min(self.length - self.position);
+        buffer[..count].copy_from_slice(&self.buffer[self.position..self.position + count]);
+        self.position += count;
+        count
+    }
+
+    /// Reads a single byte, or returns `None` at the end of the input or
+    /// after an error.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.position == self.length && !self.fill() {
+            return None;
+        }
+        self.position += 1;
+        Some(self.buffer[self.position - 1])
+    }
+
+    /// Returns the DOS error code if reading stopped because of an error.
+    pub fn error(&self) -> Option<u16> {
+        self.error
+    }
+
+    /// Refills the buffer, returning whether any bytes were read.
+    fn fill(&mut self) -> bool {
+        // Show any prompt before waiting for the input it asks for.
+        flush();
+        self.position = 0;
+        self.length = match dos::read_handle(self.handle, &mut self.buffer) {
+            Ok(length) => length as usize,
+            Err(error) => {
+                self.error = Some(error);
+                0
+            }
+        };
+        self.length > 0
+    }
+}
+
+impl Iterator for Reader {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.read_byte()
+    }
+}
+
+/// Returns the reader for standard input.
+///
+/// There is only one, so that bytes it has buffered but not handed out yet
+/// aren't lost between calls.
+///
+/// Decode the bytes with [`text::decode_iter`] to read text:
+///
+/// ```ignore
+/// for character in text::decode_iter(text::active(), io::stdin()) {
+///     ...
+/// }
+/// ```
+pub fn stdin() -> &'static mut Reader {
+    unsafe { &mut *addr_of_mut!(STDIN_READER) }
+}
+
+static mut STDIN_READER: Reader = Reader::new(STDIN);
+static mut STDOUT_WRITER: Writer = Writer::new(STDOUT);
+static mut STDERR_WRITER: Writer = Writer::new(STDERR);
+
+fn stdout() -> &'static mut Writer {
+    unsafe { &mut *addr_of_mut!(STDOUT_WRITER) }
+}
+
+fn stderr() -> &'static mut Writer {
+    unsafe { &mut *addr_of_mut!(STDERR_WRITER) }
+}
+
+/// Writes raw bytes to standard output.
 ///
 /// # Arguments
 ///
 /// * `bytes` - The bytes to write
-#[allow(dead_code)]
 pub fn write_bytes(bytes: &[u8]) {
-    bytes.iter().for_each(|&b| dos::print_character(b));
+    stdout().write_bytes(bytes);
 }
 
-/// Writes a string to the screen with CP437 encoding.
+/// Writes a string to standard output, encoded in the active code page.
 ///
 /// # Arguments
 ///
 /// * `s` - The string to write
 pub fn write_str(s: &str) {
-    s.chars().for_each(|c| dos::print_character(crate::text::cp437::encode_char_lossy(c)));
+    let _ = stdout().write_str(s);
+}
+
+/// Writes formatted text to standard output. This is what [`print!`] uses.
+pub fn write_fmt(args: fmt::Arguments) {
+    let _ = stdout().write_fmt(args);
+}
+
+/// Writes formatted text to standard error. This is what [`eprint!`] uses.
+pub fn write_error_fmt(args: fmt::Arguments) {
+    stdout().flush();
+    let _ = stderr().write_fmt(args);
+    stderr().flush();
+}
+
+/// Writes everything buffered for standard output and standard error.
+pub fn flush() {
+    stdout().flush();
+    stderr().flush();
+}
+
+/// Flushes the output buffers, puts back any hooked interrupt vectors, timer
+/// rate and A20 state, and returns to DOS.
+pub fn exit() -> ! {
+    // Leaving vectors hooked would crash DOS once our memory is reused.
+    pit::restore_rate();
+    interrupts::restore_all();
+    a20::restore();
+    flush();
+    dos::exit()
 }
 
 #[macro_export]
 macro_rules! print {
-    ($($arg:tt)*) => ($crate::io::write_str($($arg)*));
+    ($($arg:tt)*) => ($crate::io::write_fmt(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::io::write_fmt(format_args!("{}\n", format_args!($($arg)*))));
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => ($crate::io::write_error_fmt(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => ($crate::eprint!("\n"));
+    ($($arg:tt)*) => ($crate::io::write_error_fmt(format_args!("{}\n", format_args!($($arg)*))));
 }
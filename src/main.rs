@@ -3,11 +3,31 @@
 #![no_std]
 
 mod dos;
+mod a20;
+mod xms;
+mod far;
 mod panic;
 mod text;
 mod io;
+mod input;
 mod port;
 mod opn;
+mod opl;
+mod fm;
+mod speaker;
+mod dma;
+mod sb;
+mod mpu401;
+mod midi;
+mod pit;
+mod interrupts;
+mod memory;
+mod unreal;
+mod pic;
+mod platform;
+mod hal;
+#[cfg(feature = "pc98")]
+mod pc98;
 mod rng;
 mod util;
 mod video;
@@ -23,16 +43,27 @@ mod test_boxes;
 /// - Assumes a DOS environment with appropriate interrupt handlers
 #[no_mangle]
 pub unsafe extern "C" fn start() {
+    platform::init();
+    text::init();
+    if let Err(error) = memory::init() {
+        // Every allocation would fail later on without saying why.
+        eprintln!("Couldn't free unused memory: {:?}", error);
+    }
     util::seed_random();
-    dos::set_video_mode(0x13);
+    hal::graphics_mode();
 
     // Clear screen with a background color
-    video::fill_screen(0);
-    
-    // Run comprehensive box drawing tests
-    test_boxes::test_boxes();
+    hal::fill_screen(0);
     
-    video::show_mouse();
+    if platform::current() == platform::Platform::Ibm {
+        // Run comprehensive box drawing tests, which are written for mode 13h
+        test_boxes::test_boxes();
+        
+        video::show_mouse();
+    } else {
+        let (width, height) = hal::screen_size();
+        hal::draw_box(0, 0, width - 1, height - 1, 15);
+    }
     
     // Test code for random pixel plotting - kept for debugging graphics routines
     // Uncomment to test pixel plotting performance and random number generation
@@ -45,11 +76,11 @@ pub unsafe extern "C" fn start() {
     // }
 
     loop {
-        let code = dos::get_keyboard_input();
-        if code != 0 { break; }
+        if hal::read_key().is_some() { break; }
     }
 
-    dos::set_video_mode(0x03);
+    hal::text_mode();
 
     print!("Thanks for trying Rusty DOS! Nöw with CP437 support for languagés!");
+    io::exit();
 }
@@ -0,0 +1,184 @@
+//! Conventional memory blocks from DOS
+//!
+//! DOS gives a .COM program every free paragraph when it loads it, so nothing
+//! can be allocated and no child program can be run until the program gives
+//! some back. [`init`] shrinks the program's block down to the 64 KiB segment
+//! that its code, data and stack live in, which frees the rest for
+//! [`allocate`].
+//!
+//! Blocks are measured in paragraphs of 16 bytes and always start at offset 0
+//! of their segment.
+
+#![allow(dead_code)]
+
+use crate::dos;
+use crate::far::{FarPtr, FarSlice, Segment};
+
+/// The size of the program's own block: one whole segment, as the stack
+/// starts at the top of it.
+pub const PROGRAM_PARAGRAPHS: u16 = 0x1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// DOS's memory control blocks have been overwritten.
+    ArenaTrashed,
+    /// There isn't a free block big enough. `largest` is the size of the
+    /// biggest one, or for resizing, the most the block could grow to.
+    InsufficientMemory { largest: u16 },
+    /// The segment isn't the start of a block.
+    InvalidBlock,
+    /// Some other DOS error code.
+    Other(u16),
+}
+
+impl Error {
+    fn from_dos(code: u16, largest: u16) -> Self {
+        match code {
+            7 => Error::ArenaTrashed,
+            8 => Error::InsufficientMemory { largest },
+            9 => Error::InvalidBlock,
+            _ => Error::Other(code),
+        }
+    }
+}
+
+/// Shrinks the program's block to [`PROGRAM_PARAGRAPHS`], freeing the rest of
+/// conventional memory.
+///
+/// This should be called once at startup.
+pub fn init() -> Result<(), Error> {
+    dos::resize_memory(dos::code_segment(), PROGRAM_PARAGRAPHS)
+        .map_err(|(code, largest)| Error::from_dos(code, largest))
+}
+
+/// Returns the size of the largest free block in paragraphs.
+pub fn largest_free_block() -> u16 {
+    // Asking for the impossible always fails, and reports the largest block.
+    match dos::allocate_memory(0xFFFF) {
+        Ok(segment) => {
+            let _ = dos::free_memory(segment);
+            0xFFFF
+        }
+        Err((_, largest)) => largest,
+    }
+}
+
+/// Allocates a block of the given number of paragraphs.
+pub fn allocate(paragraphs: u16) -> Result<DosBlock, Error> {
+    let segment = dos::allocate_memory(paragraphs)
+        .map_err(|(code, largest)| Error::from_dos(code, largest))?;
+    Ok(DosBlock { segment, paragraphs })
+}
+
+/// Allocates a block big enough for the given number of bytes.
+pub fn allocate_bytes(bytes: u32) -> Result<DosBlock, Error> {
+    let paragraphs = bytes.div_ceil(16);
+    if paragraphs > 0xFFFF {
+        return Err(Error::InsufficientMemory { largest: largest_free_block() });
+    }
+    allocate(paragraphs as u16)
+}
+
+/// A block of conventional memory owned by the program, freed when dropped.
+///
+/// Offsets are counted from the start of the block in bytes and can go past
+/// the first 64 KiB, as they are turned into normalized far pointers.
+pub struct DosBlock {
+    segment: u16,
+    paragraphs: u16,
+}
+
+impl DosBlock {
+    /// The segment that the block starts at.
+    pub fn segment(&self) -> u16 {
+        self.segment
+    }
+
+    pub fn paragraphs(&self) -> u16 {
+        self.paragraphs
+    }
+
+    /// The size of the block in bytes.
+    pub fn len(&self) -> u32 {
+        self.paragraphs as u32 * 16
+    }
+
+    /// Grows or shrinks the block in place.
+    ///
+    /// DOS can't move blocks, so growing fails if the memory after it is in
+    /// use.
+    pub fn resize(&mut self, paragraphs: u16) -> Result<(), Error> {
+        dos::resize_memory(self.segment, paragraphs)
+            .map_err(|(code, largest)| Error::from_dos(code, largest))?;
+        self.paragraphs = paragraphs;
+        Ok(())
+    }
+
+    /// Returns a normalized pointer to a byte in the block, which can reach
+    /// the rest of it through bulk accesses.
+    ///
+    /// # Panics
+    ///
+    /// If the offset is past the end of the block.
+    pub fn ptr(&self, offset: u32) -> FarPtr<u8> {
+        assert!(offset <= self.len());
+        FarPtr::from_linear(Segment::new(self.segment).base() + offset)
+    }
+
+    /// Returns a view of the first 64 KiB of the block, or all of it if it is
+    /// smaller.
+    pub fn as_slice(&self) -> FarSlice<u8> {
+        Segment::new(self.segment).slice(0, self.len().min(0x10000) as usize)
+    }
+
+    /// Reads a byte from the block.
+    ///
+    /// # Panics
+    ///
+    /// If the offset is past the end of the block.
+    pub fn read_byte(&self, offset: u32) -> u8 {
+        assert!(offset < self.len());
+        self.ptr(offset).read()
+    }
+
+    /// Writes a byte to the block.
+    ///
+    /// # Panics
+    ///
+    /// If the offset is past the end of the block.
+    pub fn write_byte(&mut self, offset: u32, value: u8) {
+        assert!(offset < self.len());
+        self.ptr(offset).write(value);
+    }
+
+    /// Copies bytes out of the block, starting at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// If the range is past the end of the block.
+    pub fn read(&self, offset: u32, buffer: &mut [u8]) {
+        assert!(offset.checked_add(buffer.len() as u32).is_some_and(|end| end <= self.len()));
+        self.ptr(offset).read_into(buffer);
+    }
+
+    /// Copies bytes into the block, starting at `offset`.
+    ///
+    /// # Panics
+    ///
+    /// If the range is past the end of the block.
+    pub fn write(&mut self, offset: u32, bytes: &[u8]) {
+        assert!(offset.checked_add(bytes.len() as u32).is_some_and(|end| end <= self.len()));
+        self.ptr(offset).write_from(bytes);
+    }
+
+    /// Sets every byte in the block.
+    pub fn fill(&mut self, value: u8) {
+        self.ptr(0).fill(value, self.len() as usize);
+    }
+}
+
+impl Drop for DosBlock {
+    fn drop(&mut self) {
+        let _ = dos::free_memory(self.segment);
+    }
+}
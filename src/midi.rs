@@ -0,0 +1,345 @@
+//! Standard MIDI File parsing and playback
+//!
+//! The sequencer plays format 0 and format 1 files straight out of the file's
+//! bytes without copying or allocating. It has no clock of its own: call
+//! [`Sequencer::tick`] regularly with the time that has passed, and it sends
+//! every event that has become due to a [`MidiOut`].
+
+#![allow(dead_code)]
+
+use crate::mpu401::MidiOut;
+
+/// The most tracks a format 1 file can have.
+pub const MAX_TRACKS: usize = 16;
+
+/// The time between BIOS timer ticks, for sequencers driven at 18.2 Hz.
+pub const BIOS_TICK_MICROSECONDS: u32 = 54_925;
+
+/// The tempo until a file sets one: 120 beats per minute.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The data doesn't start with an `MThd` header.
+    NotMidiFile,
+    /// The file is format 2, which holds independent sequences.
+    UnsupportedFormat,
+    /// The file uses SMPTE time code instead of ticks per quarter note.
+    UnsupportedDivision,
+    /// The file has more than [`MAX_TRACKS`] tracks.
+    TooManyTracks,
+    /// A chunk runs past the end of the data.
+    Truncated,
+}
+
+/// Reads a variable-length quantity, advancing the position past it.
+fn read_variable_length(data: &[u8], position: &mut usize) -> Option<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let byte = *data.get(*position)?;
+        *position += 1;
+        value = value << 7 | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[derive(Clone, Copy)]
+struct Track<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// The tick at which the next event is due.
+    next_tick: u32,
+    running_status: u8,
+    finished: bool,
+}
+
+impl<'a> Track<'a> {
+    const EMPTY: Track<'static> = Track {
+        data: &[],
+        position: 0,
+        next_tick: 0,
+        running_status: 0,
+        finished: true,
+    };
+
+    fn new(data: &'a [u8]) -> Self {
+        let mut track = Track { data, position: 0, next_tick: 0, running_status: 0, finished: false };
+        track.read_delta();
+        track
+    }
+
+    fn read_delta(&mut self) {
+        match read_variable_length(self.data, &mut self.position) {
+            Some(delta) => self.next_tick += delta,
+            None => self.finished = true,
+        }
+    }
+
+    /// Plays the next event and reads the time until the one after it.
+    ///
+    /// Malformed data ends the track rather than the whole song.
+    fn play_event(&mut self, out: &mut dyn MidiOut, tempo: &mut u32) {
+        if self.play_event_inner(out, tempo).is_none() {
+            self.finished = true;
+        }
+        if !self.finished {
+            self.read_delta();
+        }
+    }
+
+    fn play_event_inner(&mut self, out: &mut dyn MidiOut, tempo: &mut u32) -> Option<()> {
+        let mut status = *self.data.get(self.position)?;
+        if status & 0x80 != 0 {
+            self.position += 1;
+        } else if self.running_status != 0 {
+            // Running status: the data bytes follow on from the last
+            // channel message's status.
+            status = self.running_status;
+        } else {
+            return None;
+        }
+
+        match status {
+            0x80..=0xEF => {
+                self.running_status = status;
+                let length = match status & 0xF0 {
+                    0xC0 | 0xD0 => 1,
+                    _ => 2,
+                };
+                let data = self.data.get(self.position..self.position + length)?;
+                let mut message = [status, 0, 0];
+                message[1..=length].copy_from_slice(data);
+                self.position += length;
+                out.send(&message[..=length]);
+            }
+            0xF0 | 0xF7 => {
+                self.running_status = 0;
+                let length = read_variable_length(self.data, &mut self.position)? as usize;
+                let data = self.data.get(self.position..self.position + length)?;
+                self.position += length;
+                // An F0 event omits the F0 itself, while an F7 "escape" event
+                // holds arbitrary bytes to send as they are.
+                if status == 0xF0 {
+                    out.send(&[0xF0]);
+                }
+                out.send(data);
+            }
+            0xFF => {
+                let kind = *self.data.get(self.position)?;
+                self.position += 1;
+                let length = read_variable_length(self.data, &mut self.position)? as usize;
+                let data = self.data.get(self.position..self.position + length)?;
+                self.position += length;
+                match kind {
+                    0x2F => self.finished = true,
+                    // A tempo of 0 would stop time, so it is ignored.
+                    0x51 if length == 3 && data != [0, 0, 0] => {
+                        *tempo = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+                    }
+                    _ => {}
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+/// Plays a Standard MIDI File.
+pub struct Sequencer<'a> {
+    tracks: [Track<'a>; MAX_TRACKS],
+    track_count: usize,
+    /// Ticks per quarter note.
+    division: u32,
+    /// Microseconds per quarter note.
+    tempo: u32,
+    /// The current song position in ticks.
+    tick: u32,
+    /// Elapsed time that hasn't yet added up to a whole tick, in units of
+    /// microseconds times ticks per quarter note.
+    remainder: u32,
+}
+
+impl<'a> Sequencer<'a> {
+    /// Reads the header and finds the tracks of a format 0 or 1 file.
+    pub fn new(file: &'a [u8]) -> Result<Self, Error> {
+        if file.len() < 14 || &file[0..4] != b"MThd" {
+            return Err(Error::NotMidiFile);
+        }
+        let header_length = read_u32(&file[4..8]) as usize;
+        let format = read_u16(&file[8..10]);
+        let declared_tracks = read_u16(&file[10..12]) as usize;
+        let division = read_u16(&file[12..14]);
+        if format > 1 {
+            return Err(Error::UnsupportedFormat);
+        }
+        if division & 0x8000 != 0 || division == 0 {
+            return Err(Error::UnsupportedDivision);
+        }
+        if declared_tracks > MAX_TRACKS {
+            return Err(Error::TooManyTracks);
+        }
+
+        let mut tracks = [Track::EMPTY; MAX_TRACKS];
+        let mut track_count = 0;
+        let tracks_start = header_length.checked_add(8).ok_or(Error::Truncated)?;
+        let mut chunks = file.get(tracks_start..).ok_or(Error::Truncated)?;
+        while chunks.len() >= 8 && track_count < declared_tracks {
+            let end = (read_u32(&chunks[4..8]) as usize).checked_add(8).ok_or(Error::Truncated)?;
+            let body = chunks.get(8..end).ok_or(Error::Truncated)?;
+            // Unknown chunk types are to be skipped.
+            if &chunks[0..4] == b"MTrk" {
+                tracks[track_count] = Track::new(body);
+                track_count += 1;
+            }
+            chunks = &chunks[end..];
+        }
+
+        Ok(Sequencer {
+            tracks,
+            track_count,
+            division: division as u32,
+            tempo: DEFAULT_TEMPO,
+            tick: 0,
+            remainder: 0,
+        })
+    }
+
+    /// Starts the song over from the beginning.
+    pub fn rewind(&mut self) {
+        for track in &mut self.tracks[..self.track_count] {
+            *track = Track::new(track.data);
+        }
+        self.tempo = DEFAULT_TEMPO;
+        self.tick = 0;
+        self.remainder = 0;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tracks[..self.track_count].iter().all(|track| track.finished)
+    }
+
+    /// Advances the song by the given time and sends every event that has
+    /// become due, in order across all tracks.
+    pub fn tick(&mut self, elapsed_microseconds: u32, out: &mut dyn MidiOut) {
+        // Microseconds times ticks per quarter note can pass 2^32 within a
+        // fraction of a second.
+        let total = elapsed_microseconds as u64 * self.division as u64 + self.remainder as u64;
+        self.tick = self.tick.wrapping_add((total / self.tempo as u64) as u32);
+        self.remainder = (total % self.tempo as u64) as u32;
+
+        let now = self.tick;
+        loop {
+            let due = self.tracks[..self.track_count].iter_mut()
+                .filter(|track| !track.finished && track.next_tick <= now)
+                .min_by_key(|track| track.next_tick);
+            match due {
+                Some(track) => track.play_event(out, &mut self.tempo),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Recorder {
+        bytes: [u8; 64],
+        length: usize,
+    }
+
+    impl MidiOut for Recorder {
+        fn send(&mut self, message: &[u8]) {
+            self.bytes[self.length..self.length + message.len()].copy_from_slice(message);
+            self.length += message.len();
+        }
+    }
+
+    const SONG: [u8; 47] = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96,
+        b'M', b'T', b'r', b'k', 0, 0, 0, 25,
+        // Set the tempo to 250 ms per quarter note.
+        0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90,
+        // Note on, then a second one using running status.
+        0x00, 0x90, 60, 100,
+        0x00, 64, 100,
+        // A quarter note later, both notes off.
+        0x60, 0x80, 60, 0,
+        0x00, 64, 0,
+        0x00, 0xFF, 0x2F, 0x00,
+    ];
+
+    #[test]
+    fn test_variable_length() {
+        let mut position = 0;
+        assert_eq!(read_variable_length(&[0x81, 0x80, 0x00], &mut position), Some(0x4000));
+        assert_eq!(position, 3);
+        assert_eq!(read_variable_length(&[0x81], &mut 0), None);
+    }
+
+    #[test]
+    fn test_sequencer() {
+        let mut sequencer = Sequencer::new(&SONG).unwrap();
+        let mut recorder = Recorder { bytes: [0; 64], length: 0 };
+
+        sequencer.tick(0, &mut recorder);
+        assert_eq!(&recorder.bytes[..recorder.length], &[0x90, 60, 100, 0x90, 64, 100]);
+
+        // 200 ms is still short of the quarter note at the new tempo.
+        sequencer.tick(200_000, &mut recorder);
+        assert_eq!(recorder.length, 6);
+        assert!(!sequencer.is_finished());
+
+        sequencer.tick(50_000, &mut recorder);
+        assert_eq!(&recorder.bytes[6..recorder.length], &[0x80, 60, 0, 0x80, 64, 0]);
+        assert!(sequencer.is_finished());
+    }
+
+    #[test]
+    fn test_long_tick_at_high_division() {
+        let mut song = SONG;
+        song[12..14].copy_from_slice(&[0x7F, 0xFF]);
+        let mut sequencer = Sequencer::new(&song).unwrap();
+        let mut recorder = Recorder { bytes: [0; 64], length: 0 };
+        sequencer.tick(2_000_000, &mut recorder);
+        // Two seconds at the default 500 ms per quarter note.
+        assert_eq!(sequencer.tick, 4 * 0x7FFF);
+    }
+
+    #[test]
+    fn test_ignores_zero_tempo() {
+        let mut song = SONG;
+        song[26..29].copy_from_slice(&[0, 0, 0]);
+        let mut sequencer = Sequencer::new(&song).unwrap();
+        let mut recorder = Recorder { bytes: [0; 64], length: 0 };
+        sequencer.tick(0, &mut recorder);
+        assert_eq!(sequencer.tempo, DEFAULT_TEMPO);
+    }
+
+    #[test]
+    fn test_rejects_huge_chunk_length() {
+        let mut song = SONG;
+        song[18..22].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(Sequencer::new(&song).err(), Some(Error::Truncated));
+    }
+
+    #[test]
+    fn test_rejects_format_2() {
+        let mut song = SONG;
+        song[9] = 2;
+        assert_eq!(Sequencer::new(&song).err(), Some(Error::UnsupportedFormat));
+    }
+}
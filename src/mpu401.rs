@@ -0,0 +1,129 @@
+//! Roland MPU-401 MIDI interface in UART mode
+//!
+//! In UART mode the MPU-401 is a plain serial pipe: every byte written to the
+//! data port is sent on the MIDI cable as-is. The intelligent mode with its
+//! on-board sequencer isn't used, and many clones don't implement it anyway.
+
+#![allow(dead_code)]
+
+use bitflags::bitflags;
+use crate::port::{inb, outb};
+
+/// The usual base port, which can also be found in the `P` setting of the
+/// `BLASTER` environment variable.
+pub const DEFAULT_BASE: u16 = 0x330;
+
+const RESET: u8 = 0xFF;
+const ENTER_UART: u8 = 0x3F;
+const ACKNOWLEDGE: u8 = 0xFE;
+
+/// How many times to poll the status port before deciding that the interface
+/// isn't there. With no card present, the port reads as 0xFF forever.
+const TIMEOUT: u32 = 0x10000;
+
+bitflags! {
+    pub struct Status: u8 {
+        /// The interface can't accept a byte yet.
+        const OUTPUT_NOT_READY = 0b0100_0000;
+        /// There is no byte waiting to be read.
+        const INPUT_NOT_READY  = 0b1000_0000;
+    }
+}
+
+/// Somewhere to send MIDI messages.
+pub trait MidiOut {
+    /// Sends one complete message, including its status byte.
+    fn send(&mut self, message: &[u8]);
+}
+
+/// An MPU-401 switched into UART mode.
+///
+/// The interface is reset back to intelligent mode when this is dropped.
+pub struct Mpu401 {
+    base: u16,
+}
+
+impl Mpu401 {
+    /// Resets the interface at the given base port and switches it to UART
+    /// mode, or returns `None` if nothing acknowledges the commands.
+    pub fn new(base: u16) -> Option<Self> {
+        let mpu = Mpu401 { base };
+        // A reset isn't acknowledged if the interface is already in UART
+        // mode, so try twice.
+        if !mpu.command(RESET) && !mpu.command(RESET) {
+            return None;
+        }
+        if !mpu.command(ENTER_UART) {
+            return None;
+        }
+        Some(mpu)
+    }
+
+    pub fn read_status(&self) -> Status {
+        Status::from_bits_truncate(unsafe { inb(self.base + 1) })
+    }
+
+    /// Sends a single byte, waiting for the interface to be ready.
+    ///
+    /// Returns `false` if it never became ready.
+    pub fn write(&self, byte: u8) -> bool {
+        if !self.wait_for_output_ready() {
+            return false;
+        }
+        unsafe { outb(self.base, byte); }
+        true
+    }
+
+    /// Reads a byte if one has arrived.
+    pub fn read(&self) -> Option<u8> {
+        if self.read_status().contains(Status::INPUT_NOT_READY) {
+            None
+        } else {
+            Some(unsafe { inb(self.base) })
+        }
+    }
+
+    /// Turns off every note on every channel.
+    pub fn all_notes_off(&mut self) {
+        for channel in 0..16 {
+            // All Notes Off, then All Sound Off for synths that ignore it.
+            self.send(&[0xB0 | channel, 123, 0]);
+            self.send(&[0xB0 | channel, 120, 0]);
+        }
+    }
+
+    /// Writes a command and waits for it to be acknowledged.
+    fn command(&self, command: u8) -> bool {
+        if !self.wait_for_output_ready() {
+            return false;
+        }
+        unsafe { outb(self.base + 1, command); }
+        for _ in 0..0xFFFFu16 {
+            if self.read() == Some(ACKNOWLEDGE) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Polls the status until the interface can accept a byte, or gives up
+    /// after [`TIMEOUT`] tries.
+    fn wait_for_output_ready(&self) -> bool {
+        (0..TIMEOUT).any(|_| !self.read_status().contains(Status::OUTPUT_NOT_READY))
+    }
+}
+
+impl MidiOut for Mpu401 {
+    fn send(&mut self, message: &[u8]) {
+        // Stop at the first byte that times out rather than waiting out the
+        // timeout again for each of the rest.
+        let _ = message.iter().all(|&byte| self.write(byte));
+    }
+}
+
+impl Drop for Mpu401 {
+    fn drop(&mut self) {
+        self.all_notes_off();
+        self.command(RESET);
+    }
+}
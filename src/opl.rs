@@ -0,0 +1,142 @@
+//! Yamaha YM3812 (OPL2) driver for the AdLib and compatible sound cards
+//!
+//! The chip sits behind an address port at 388h and a data port at 389h.
+//! It is slow to latch writes: after selecting a register it needs 3.3 µs,
+//! and after writing data it needs 23 µs before the next access. Reading the
+//! status port takes roughly a microsecond on the ISA bus, so the delays are
+//! produced by reading it repeatedly, which works regardless of CPU speed.
+
+#![allow(dead_code)]
+
+use bitflags::bitflags;
+use crate::fm::{self, FmChip, Instrument, Operator};
+use crate::port::{inb, outb};
+
+const ADDRESS_PORT: u16 = 0x388;
+const DATA_PORT: u16 = 0x389;
+
+bitflags! {
+    pub struct Status: u8 {
+        const TIMER2_EXPIRED = 0b0010_0000;
+        const TIMER1_EXPIRED = 0b0100_0000;
+        const IRQ            = 0b1000_0000;
+    }
+}
+
+/// Operator register offsets for the modulator of each channel. The carrier
+/// is always three higher.
+const MODULATOR_OFFSETS: [u8; 9] = [0x00, 0x01, 0x02, 0x08, 0x09, 0x0A, 0x10, 0x11, 0x12];
+
+/// F-numbers for C through B, for use with the block of the note's octave.
+const F_NUMBERS: [u16; 12] = [
+    0x157, 0x16B, 0x181, 0x198, 0x1B0, 0x1CA, 0x1E5, 0x202, 0x220, 0x241, 0x263, 0x287,
+];
+
+#[inline(always)]
+pub fn write_address(address: u8) {
+    unsafe { outb(ADDRESS_PORT, address); }
+    delay(6);
+}
+
+#[inline(always)]
+pub fn write_data(data: u8) {
+    unsafe { outb(DATA_PORT, data); }
+    delay(35);
+}
+
+#[inline(always)]
+pub fn read_status() -> Status {
+    Status::from_bits_truncate(unsafe { inb(ADDRESS_PORT) })
+}
+
+#[inline(always)]
+pub fn write(address: u8, data: u8) {
+    write_address(address);
+    write_data(data);
+}
+
+/// Waits by reading the status port the given number of times.
+fn delay(reads: u16) {
+    for _ in 0..reads {
+        unsafe { inb(ADDRESS_PORT); }
+    }
+}
+
+/// Checks for an OPL2 by starting timer 1 and seeing whether it expires.
+pub fn detect() -> bool {
+    // Reset both timers, then clear any pending IRQ.
+    write(0x04, 0x60);
+    write(0x04, 0x80);
+    let before = read_status();
+
+    // Start timer 1 at its shortest period (80 µs) and wait long enough for
+    // it to expire.
+    write(0x02, 0xFF);
+    write(0x04, 0x21);
+    delay(200);
+    let after = read_status();
+
+    write(0x04, 0x60);
+    write(0x04, 0x80);
+
+    let expired = Status::IRQ | Status::TIMER1_EXPIRED;
+    before.bits() & 0xE0 == 0 && after.bits() & 0xE0 == expired.bits()
+}
+
+/// An initialized OPL2 chip.
+pub struct Opl2 {
+    /// The last value written to each channel's B0h register, so that key off
+    /// can keep the frequency and only clear the key on bit.
+    key_registers: [u8; 9],
+}
+
+impl Opl2 {
+    /// Detects the chip and resets every register to a silent state.
+    pub fn new() -> Option<Self> {
+        if !detect() {
+            return None;
+        }
+        for register in 0x01..=0xF5 {
+            write(register, 0);
+        }
+        // Allow operators to use waveforms other than a sine wave.
+        write(0x01, 0x20);
+        Some(Opl2 { key_registers: [0; 9] })
+    }
+
+    fn write_operator(offset: u8, operator: &Operator) {
+        let sustained = if operator.sustained { 0x20 } else { 0 };
+        write(0x20 + offset, sustained | (operator.multiple & 0x0F));
+        write(0x40 + offset, (operator.key_scale_level & 0x03) << 6 | (operator.total_level & 0x3F));
+        write(0x60 + offset, (operator.attack & 0x0F) << 4 | (operator.decay & 0x0F));
+        write(0x80 + offset, (operator.sustain_level & 0x0F) << 4 | (operator.release & 0x0F));
+        write(0xE0 + offset, operator.waveform & 0x03);
+    }
+}
+
+impl FmChip for Opl2 {
+    const CHANNELS: u8 = 9;
+
+    fn set_instrument(&mut self, channel: u8, instrument: &Instrument) {
+        let offset = MODULATOR_OFFSETS[channel as usize];
+        Self::write_operator(offset, &instrument.modulator);
+        Self::write_operator(offset + 3, &instrument.carrier);
+        let connection = if instrument.additive { 1 } else { 0 };
+        write(0xC0 + channel, (instrument.feedback & 0x07) << 1 | connection);
+    }
+
+    fn note_on(&mut self, channel: u8, note: u8) {
+        let (block, semitone) = fm::split_note(note, -1);
+        let f_number = F_NUMBERS[semitone];
+        let key = 0x20 | block << 2 | (f_number >> 8) as u8;
+        write(0xA0 + channel, f_number as u8);
+        write(0xB0 + channel, key);
+        self.key_registers[channel as usize] = key;
+    }
+
+    fn note_off(&mut self, channel: u8) {
+        let key = &mut self.key_registers[channel as usize];
+        *key &= !0x20;
+        write(0xB0 + channel, *key);
+    }
+}
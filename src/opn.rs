@@ -1,4 +1,5 @@
 use bitflags::bitflags;
+use crate::fm::{self, FmChip, Instrument, Operator};
 use crate::port::{inb, outb};
 
 bitflags! {
@@ -39,3 +40,87 @@ pub fn write(address: u8, data: u8) {
     write_address(address);
     write_data(data);
 }
+
+/// Waits until the chip is ready to accept another write.
+#[inline(always)]
+#[allow(dead_code)]
+pub fn wait_ready() {
+    while read_status().contains(Status::BUSY) {}
+}
+
+/// Operator register offsets in operator order (1, 2, 3, 4). The chip's
+/// register layout interleaves them as 1, 3, 2, 4.
+#[allow(dead_code)]
+const OPERATOR_OFFSETS: [u8; 4] = [0x0, 0x8, 0x4, 0xC];
+
+/// F-numbers for C through B, for use with a block one above the note's
+/// octave, at the PC-98's 3.9936 MHz clock.
+#[allow(dead_code)]
+const F_NUMBERS: [u16; 12] = [
+    0x26A, 0x28F, 0x2B6, 0x2DF, 0x30B, 0x339, 0x36A, 0x39E, 0x3D5, 0x410, 0x44E, 0x48F,
+];
+
+/// The FM part of the PC-98's YM2203.
+///
+/// Instruments only use operators 1 and 2. Operators 3 and 4 are muted and
+/// never keyed on.
+#[allow(dead_code)]
+pub struct Opn;
+
+#[allow(dead_code)]
+impl Opn {
+    /// Silences all FM channels.
+    pub fn new() -> Self {
+        let mut opn = Opn;
+        for channel in 0..Self::CHANNELS {
+            for &offset in &OPERATOR_OFFSETS {
+                opn.write_ready(0x40 + offset + channel, 0x7F);
+            }
+            opn.write_ready(0x28, channel);
+        }
+        opn
+    }
+
+    fn write_ready(&mut self, address: u8, data: u8) {
+        wait_ready();
+        write(address, data);
+    }
+
+    fn write_operator(&mut self, register: u8, operator: &Operator) {
+        self.write_ready(0x30 + register, operator.multiple & 0x0F);
+        self.write_ready(0x40 + register, (operator.total_level & 0x3F) << 1);
+        self.write_ready(0x50 + register, (operator.attack & 0x0F) << 1 | 1);
+        self.write_ready(0x60 + register, (operator.decay & 0x0F) << 1);
+        let sustain_rate = if operator.sustained { 0 } else { operator.release & 0x0F };
+        self.write_ready(0x70 + register, sustain_rate << 1);
+        self.write_ready(0x80 + register, (operator.sustain_level & 0x0F) << 4 | (operator.release & 0x0F));
+    }
+}
+
+impl FmChip for Opn {
+    const CHANNELS: u8 = 3;
+
+    fn set_instrument(&mut self, channel: u8, instrument: &Instrument) {
+        self.write_operator(OPERATOR_OFFSETS[0] + channel, &instrument.modulator);
+        self.write_operator(OPERATOR_OFFSETS[1] + channel, &instrument.carrier);
+        self.write_ready(0x40 + OPERATOR_OFFSETS[2] + channel, 0x7F);
+        self.write_ready(0x40 + OPERATOR_OFFSETS[3] + channel, 0x7F);
+        // Algorithm 4 chains 1 into 2 (and 3 into 4); algorithm 7 outputs
+        // every operator directly.
+        let algorithm = if instrument.additive { 7 } else { 4 };
+        self.write_ready(0xB0 + channel, (instrument.feedback & 0x07) << 3 | algorithm);
+    }
+
+    fn note_on(&mut self, channel: u8, note: u8) {
+        let (block, semitone) = fm::split_note(note, 0);
+        let f_number = F_NUMBERS[semitone];
+        // The high byte must be written first, as it is latched by the low.
+        self.write_ready(0xA4 + channel, block << 3 | (f_number >> 8) as u8);
+        self.write_ready(0xA0 + channel, f_number as u8);
+        self.write_ready(0x28, 0x30 | channel);
+    }
+
+    fn note_off(&mut self, channel: u8) {
+        self.write_ready(0x28, channel);
+    }
+}
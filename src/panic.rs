@@ -1,10 +1,11 @@
 use core::panic::PanicInfo;
 
-use crate::dos;
+use crate::io;
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    dos::print("\nPanic!$".as_ptr());
-    dos::exit();
-    loop {}
+    // Standard error stays on the screen when standard output is redirected.
+    crate::eprintln!("\nPanic!");
+    // This puts back hooked vectors, the timer rate and A20 on the way out.
+    io::exit()
 }
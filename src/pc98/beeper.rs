@@ -0,0 +1,60 @@
+//! PC-98 built-in beeper
+//!
+//! The beeper is driven by counter 1 of the 8253 timer, the way the IBM PC
+//! speaker is driven by channel 2, and gated by bit 3 of the 8255 system
+//! port C. The counter's clock depends on the model: 2.4576 MHz on machines
+//! with a 5 or 10 MHz system clock, and 1.9968 MHz on 8 MHz ones.
+
+#![allow(dead_code)]
+
+use crate::dos;
+use crate::port::outb;
+
+const COUNTER_PORT: u16 = 0x3FDB;
+const MODE_PORT: u16 = 0x3FDF;
+
+/// The mode register for the 8255 system port C, which can set or reset its
+/// bits individually.
+const SYSTEM_PORT_MODE: u16 = 0x37;
+
+/// The BIOS work area byte whose bit 7 is set on 8 MHz machines.
+const SYSTEM_FLAGS: u16 = 0x0501;
+
+/// Returns the frequency of the clock driving the counter.
+pub fn clock_frequency() -> u32 {
+    if dos::read_far_byte(0x0000, SYSTEM_FLAGS) & 0x80 != 0 { 1_996_800 } else { 2_457_600 }
+}
+
+/// Sets the pitch of the beeper without turning it on or off.
+///
+/// Frequencies below 38 Hz cannot be produced and are clamped.
+pub fn set_frequency(frequency: u16) {
+    let divisor = (clock_frequency() / frequency.max(38) as u32) as u16;
+    unsafe {
+        // Counter 1, low byte then high byte, mode 3 (square wave).
+        outb(MODE_PORT, 0b0111_0110);
+        outb(COUNTER_PORT, divisor as u8);
+        outb(COUNTER_PORT, (divisor >> 8) as u8);
+    }
+}
+
+/// Starts a beep at the given frequency, or stops it if the frequency is 0.
+pub fn tone(frequency: u16) {
+    if frequency == 0 {
+        silence();
+        return;
+    }
+    set_frequency(frequency);
+    set_enabled(true);
+}
+
+/// Stops the beep.
+pub fn silence() {
+    set_enabled(false);
+}
+
+/// Turns the beeper on or off.
+pub fn set_enabled(on: bool) {
+    // Bit 3 of port C gates the beeper, and is active low.
+    unsafe { outb(SYSTEM_PORT_MODE, if on { 0x06 } else { 0x07 }); }
+}
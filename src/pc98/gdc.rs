@@ -0,0 +1,108 @@
+//! NEC µPD7220 graphics display controllers
+//!
+//! The PC-98 has two: the master drives the text layer and the slave drives
+//! the graphics planes. Each takes a command byte followed by parameter
+//! bytes through a small FIFO.
+
+#![allow(dead_code)]
+
+use bitflags::bitflags;
+use crate::port::{inb, outb};
+
+bitflags! {
+    pub struct Status: u8 {
+        const DATA_READY  = 0b0000_0001;
+        const FIFO_FULL   = 0b0000_0010;
+        const FIFO_EMPTY  = 0b0000_0100;
+        const DRAWING     = 0b0000_1000;
+        const DMA_EXECUTE = 0b0001_0000;
+        const VSYNC       = 0b0010_0000;
+        const HBLANK      = 0b0100_0000;
+        const LIGHT_PEN   = 0b1000_0000;
+    }
+}
+
+const START: u8 = 0x0D;
+const STOP: u8 = 0x0C;
+const CURSOR_WRITE: u8 = 0x49;
+const CURSOR_FORM: u8 = 0x4B;
+
+/// One of the two display controllers.
+pub struct Gdc {
+    /// The status port when read and the parameter port when written. The
+    /// command port is two above it.
+    port: u16,
+}
+
+/// The controller for the text layer.
+pub const TEXT: Gdc = Gdc { port: 0x60 };
+
+/// The controller for the graphics planes.
+pub const GRAPHICS: Gdc = Gdc { port: 0xA0 };
+
+impl Gdc {
+    pub fn read_status(&self) -> Status {
+        Status::from_bits_truncate(unsafe { inb(self.port) })
+    }
+
+    /// Sends a command and its parameters, waiting for room in the FIFO.
+    pub fn command(&self, command: u8, parameters: &[u8]) {
+        self.wait_for_fifo();
+        unsafe { outb(self.port + 2, command); }
+        for &parameter in parameters {
+            self.wait_for_fifo();
+            unsafe { outb(self.port, parameter); }
+        }
+    }
+
+    fn wait_for_fifo(&self) {
+        while self.read_status().contains(Status::FIFO_FULL) {}
+    }
+
+    /// Waits until every queued command has been carried out.
+    pub fn wait_until_idle(&self) {
+        while !self.read_status().contains(Status::FIFO_EMPTY) {}
+        while self.read_status().contains(Status::DRAWING) {}
+    }
+
+    /// Waits for the start of the next vertical retrace.
+    pub fn wait_for_vsync(&self) {
+        while self.read_status().contains(Status::VSYNC) {}
+        while !self.read_status().contains(Status::VSYNC) {}
+    }
+
+    /// Turns the controller's display output on.
+    pub fn start(&self) {
+        self.command(START, &[]);
+    }
+
+    /// Turns the controller's display output off.
+    pub fn stop(&self) {
+        self.command(STOP, &[]);
+    }
+
+    /// Moves the cursor, which is also where drawing commands start, to a
+    /// word address in display memory.
+    pub fn set_cursor(&self, address: u32) {
+        self.command(CURSOR_WRITE, &[
+            address as u8,
+            (address >> 8) as u8,
+            (address >> 16) as u8 & 0x03,
+        ]);
+    }
+
+    /// Sets the shape of the text cursor.
+    ///
+    /// `lines_per_row` is the height of a character row, and the cursor covers
+    /// the lines from `top` to `bottom` within it.
+    pub fn set_cursor_form(&self, visible: bool, lines_per_row: u8, top: u8, bottom: u8) {
+        let visible = if visible { 0x80 } else { 0 };
+        // Blink rate of 12 frames, split across the second and third bytes.
+        let blink_rate: u8 = 12;
+        self.command(CURSOR_FORM, &[
+            visible | (lines_per_row - 1) & 0x1F,
+            (blink_rate & 0x03) << 6 | top & 0x1F,
+            (bottom & 0x1F) << 3 | blink_rate >> 2,
+        ]);
+    }
+}
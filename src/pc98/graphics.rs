@@ -0,0 +1,152 @@
+//! PC-98 graphics planes in 640x400, 16 color mode
+//!
+//! Graphics memory is split into four bitplanes, one per bit of the color
+//! index: blue at A800h, red at B000h, green at B800h and intensity at E000h.
+//! Each line is 80 bytes per plane, with the leftmost pixel in bit 7. There
+//! are two pages of planes. [`init`] shows and draws to page 0; callers that
+//! want to double buffer call [`flip`] to show one while drawing the other.
+//!
+//! The drawing functions mirror `video`'s, so code that only plots pixels and
+//! boxes can target either machine.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::ptr::{addr_of, read_volatile};
+use crate::far::Segment;
+use crate::pc98::gdc;
+use crate::port::outb;
+
+pub const WIDTH: u16 = 640;
+pub const HEIGHT: u16 = 400;
+
+const BYTES_PER_LINE: u16 = WIDTH / 8;
+
+/// The plane segments, in the order of the color index bits they hold.
+const PLANES: [u16; 4] = [0xA800, 0xB000, 0xB800, 0xE000];
+
+const MODE_PORT: u16 = 0x6A;
+const DISPLAY_PAGE_PORT: u16 = 0xA4;
+const DRAW_PAGE_PORT: u16 = 0xA6;
+const PALETTE_INDEX_PORT: u16 = 0xA8;
+const PALETTE_GREEN_PORT: u16 = 0xAA;
+const PALETTE_RED_PORT: u16 = 0xAC;
+const PALETTE_BLUE_PORT: u16 = 0xAE;
+
+/// The colors the BIOS sets up, as 4-bit red, green and blue levels.
+pub const DEFAULT_PALETTE: [(u8, u8, u8); 16] = [
+    (0x0, 0x0, 0x0), (0x0, 0x0, 0x7), (0x7, 0x0, 0x0), (0x7, 0x0, 0x7),
+    (0x0, 0x7, 0x0), (0x0, 0x7, 0x7), (0x7, 0x7, 0x0), (0x7, 0x7, 0x7),
+    (0x4, 0x4, 0x4), (0x0, 0x0, 0xF), (0xF, 0x0, 0x0), (0xF, 0x0, 0xF),
+    (0x0, 0xF, 0x0), (0x0, 0xF, 0xF), (0xF, 0xF, 0x0), (0xF, 0xF, 0xF),
+];
+
+/// The page being drawn to.
+static mut DRAW_PAGE: u8 = 0;
+
+/// Sets up 640x400 with 16 colors and turns the graphics layer on, showing
+/// and drawing to page 0.
+pub fn init() {
+    unsafe {
+        // Color, 400 lines, using both pages.
+        asm!(
+            "int 18h",
+            inout("ax") 0x4200u16 => _,
+            inout("cx") 0xC000u16 => _,
+        );
+        // Select the analog palette instead of the 8 color digital one.
+        outb(MODE_PORT, 0x01);
+    }
+    gdc::GRAPHICS.start();
+    for (index, &(red, green, blue)) in DEFAULT_PALETTE.iter().enumerate() {
+        set_palette(index as u8, red, green, blue);
+    }
+    set_pages(0, 0);
+}
+
+/// Turns the graphics layer off, leaving the text layer showing.
+pub fn shutdown() {
+    gdc::GRAPHICS.stop();
+    set_pages(0, 0);
+}
+
+/// Sets a palette entry to 4-bit red, green and blue levels.
+pub fn set_palette(index: u8, red: u8, green: u8, blue: u8) {
+    unsafe {
+        outb(PALETTE_INDEX_PORT, index & 0x0F);
+        outb(PALETTE_GREEN_PORT, green & 0x0F);
+        outb(PALETTE_RED_PORT, red & 0x0F);
+        outb(PALETTE_BLUE_PORT, blue & 0x0F);
+    }
+}
+
+/// Chooses which page is shown and which one the drawing functions write to.
+pub fn set_pages(display: u8, draw: u8) {
+    unsafe {
+        outb(DISPLAY_PAGE_PORT, display & 1);
+        outb(DRAW_PAGE_PORT, draw & 1);
+        DRAW_PAGE = draw & 1;
+    }
+}
+
+/// Shows the page that was being drawn to and starts drawing to the other
+/// one, waiting for the vertical retrace so the swap doesn't tear.
+pub fn flip() {
+    let shown = unsafe { read_volatile(addr_of!(DRAW_PAGE)) };
+    gdc::GRAPHICS.wait_for_vsync();
+    set_pages(shown, shown ^ 1);
+}
+
+/// Fills the entire page being drawn to with a color.
+///
+/// # Arguments
+///
+/// * `color` - The palette index (0-15) to fill the screen with
+pub fn fill_screen(color: u8) {
+    for (bit, &segment) in PLANES.iter().enumerate() {
+        let fill = if color & 1 << bit != 0 { 0xFF } else { 0 };
+        Segment::new(segment).slice(0, (BYTES_PER_LINE * HEIGHT) as usize).fill(fill);
+    }
+}
+
+/// Plots a single pixel on the page being drawn to.
+///
+/// # Arguments
+///
+/// * `x` - The x-coordinate (0-639)
+/// * `y` - The y-coordinate (0-399)
+/// * `color` - The palette index (0-15) for the pixel color
+pub fn plot_pixel(x: u16, y: u16, color: u8) {
+    if x >= WIDTH || y >= HEIGHT {
+        return;
+    }
+    let offset = y * BYTES_PER_LINE + x / 8;
+    let mask = 0x80 >> (x % 8);
+    for (bit, &segment) in PLANES.iter().enumerate() {
+        let pointer = Segment::new(segment).ptr::<u8>(offset);
+        let byte = pointer.read();
+        pointer.write(if color & 1 << bit != 0 { byte | mask } else { byte & !mask });
+    }
+}
+
+/// Draws a rectangular box outline on the page being drawn to.
+///
+/// # Arguments
+///
+/// * `x` - The x-coordinate of the top-left corner
+/// * `y` - The y-coordinate of the top-left corner
+/// * `w` - The width of the box
+/// * `h` - The height of the box
+/// * `color` - The palette index (0-15) for the box color
+pub fn draw_box(x: u16, y: u16, w: u16, h: u16, color: u8) {
+    let max_x = x.saturating_add(w);
+    let max_y = y.saturating_add(h);
+    for i in y..=max_y {
+        plot_pixel(x, i, color);
+        plot_pixel(max_x, i, color);
+    }
+    for i in x..=max_x {
+        plot_pixel(i, y, color);
+        plot_pixel(i, max_y, color);
+    }
+}
@@ -0,0 +1,114 @@
+//! PC-98 keyboard input
+//!
+//! Keys are read through BIOS interrupt 18h, which reports NEC key codes
+//! rather than IBM scan codes. [`read_key`] translates them so that the rest
+//! of the program sees the same [`Key`] values on both machines, as far as
+//! the two keyboards have keys in common.
+//!
+//! The BIOS also keeps a bitmap of the keys currently held down in its work
+//! area, which [`is_pressed`] reads directly.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use bitflags::bitflags;
+use crate::dos;
+use crate::hal::Key;
+
+/// The key status bitmap in the BIOS work area, one bit per key code.
+const KEY_STATUS: u16 = 0x052A;
+
+bitflags! {
+    pub struct Shift: u8 {
+        const SHIFT = 0b0000_0001;
+        const CAPS  = 0b0000_0010;
+        const KANA  = 0b0000_0100;
+        const GRPH  = 0b0000_1000;
+        const CTRL  = 0b0001_0000;
+    }
+}
+
+/// The IBM scan code for each NEC key code, or 0 for keys the IBM keyboard
+/// doesn't have, such as COPY.
+const SCAN_CODES: [u8; 0x80] = [
+    // ESC, 1-0, -, ^, ¥, BS, TAB
+    0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x7D, 0x0E, 0x0F,
+    // Q-P, @, [, RETURN, A, S, D
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x1E, 0x1F, 0x20,
+    // F-L, ;, :, ], Z-M
+    0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F, 0x30, 0x31, 0x32,
+    // ,, ., /, _, SPACE, XFER, ROLL UP, ROLL DOWN, INS, DEL, arrows, HOME/CLR, HELP
+    0x33, 0x34, 0x35, 0x73, 0x39, 0x79, 0x51, 0x49, 0x52, 0x53, 0x48, 0x4B, 0x4D, 0x50, 0x47, 0x4F,
+    // Keypad -, /, 7, 8, 9, *, 4, 5, 6, +, 1, 2, 3, =, 0, ,
+    0x4A, 0x35, 0x47, 0x48, 0x49, 0x37, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F, 0x50, 0x51, 0x00, 0x52, 0x00,
+    // Keypad ., NFER, vf1-vf5
+    0x53, 0x7B, 0x57, 0x58, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // STOP, COPY, f1-f10
+    0x46, 0x00, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x40, 0x41, 0x42, 0x43, 0x44, 0x00, 0x00, 0x00, 0x00,
+    // SHIFT, CAPS, KANA, GRPH, CTRL
+    0x2A, 0x3A, 0x70, 0x38, 0x1D, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Translates a key code into the scan code that INT 16h would report for the
+/// same key.
+pub fn to_scan_code(code: u8) -> u8 {
+    match code {
+        // SHIFT and CTRL with f1-f10 have codes of their own, as they do on
+        // the IBM side.
+        0x82..=0x8B => code - 0x82 + 0x54,
+        0x92..=0x9B => code - 0x92 + 0x5E,
+        _ => SCAN_CODES[(code & 0x7F) as usize],
+    }
+}
+
+/// Gets keyboard input without blocking using BIOS interrupt 18h.
+///
+/// # Returns
+///
+/// The key code in the high byte and the character in the low byte, or `None`
+/// if no key is pressed
+pub fn get_key() -> Option<u16> {
+    let key: u16;
+    let available: u8;
+    unsafe {
+        asm!(
+            "push bx",
+            "mov ah, 01h",
+            "int 18h",
+            "mov cl, bh",
+            "test cl, cl",
+            "jz 2f",
+            "mov ah, 00h",
+            "int 18h",
+            "2:",
+            "pop bx",
+            out("ax") key,
+            out("cl") available,
+        );
+    }
+    if available != 0 { Some(key) } else { None }
+}
+
+/// Returns the next key press without waiting, with its key code translated
+/// to an IBM scan code.
+pub fn read_key() -> Option<Key> {
+    get_key().map(|key| Key { scan_code: to_scan_code((key >> 8) as u8), character: key as u8 })
+}
+
+/// Returns whether the key with the given NEC key code is being held down.
+pub fn is_pressed(code: u8) -> bool {
+    let byte = dos::read_far_byte(0x0000, KEY_STATUS + (code >> 3) as u16);
+    byte & 1 << (code & 7) != 0
+}
+
+/// Reads the state of the shift keys using BIOS interrupt 18h.
+pub fn shift_state() -> Shift {
+    let state: u16;
+    unsafe {
+        asm!(
+            "int 18h",
+            inout("ax") 0x0200u16 => state,
+        );
+    }
+    Shift::from_bits_truncate(state as u8)
+}
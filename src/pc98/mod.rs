@@ -0,0 +1,36 @@
+//! NEC PC-98 specific hardware
+//!
+//! The PC-98 BIOS services live on INT 18h (keyboard and text display) rather
+//! than the IBM's INT 10h and INT 16h.
+
+#![allow(dead_code)]
+
+pub mod beeper;
+pub mod gdc;
+pub mod graphics;
+pub mod keyboard;
+pub mod text;
+
+use core::arch::asm;
+
+/// Shows the text layer using BIOS interrupt 18h.
+pub fn show_text() {
+    unsafe {
+        asm!(
+            "int 18h",
+            inout("ax") 0x0C00u16 => _,
+        );
+    }
+}
+
+/// Clears the text layer to spaces using BIOS interrupt 18h.
+pub fn clear_text() {
+    unsafe {
+        asm!(
+            "int 18h",
+            // White, visible attribute and a space.
+            in("dx") 0xE120u16,
+            inout("ax") 0x1600u16 => _,
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! PC-98 text layer console with Shift-JIS output
+//!
+//! The text layer is an 80 by 25 grid of cells with a hardware character
+//! generator holding the full JIS X 0208 set, so Japanese text can be
+//! displayed without drawing any glyphs. Each cell has a character word in
+//! text VRAM at A000h and an attribute in attribute VRAM at A200h, both two
+//! bytes per cell.
+//!
+//! Half-width characters take the character code in the low byte. Full-width
+//! characters span two cells, each holding the JIS row minus 20h in the low
+//! byte and the JIS cell in the high byte, with bit 7 of the low byte set in
+//! the right-hand cell.
+//!
+//! Text is given as Shift-JIS, either from `sjis!` literals or bytes read at
+//! runtime, and converted to JIS as it is written:
+//!
+//! ```ignore
+//! let mut console = TextConsole::new();
+//! console.clear();
+//! console.write_sjis(sjis!("こんにちは、世界！\r\n"));
+//! ```
+
+#![allow(dead_code)]
+
+use crate::far::Segment;
+use crate::pc98::gdc;
+use crate::text::sjis;
+
+pub const COLUMNS: u8 = 80;
+pub const ROWS: u8 = 25;
+
+const TEXT_SEGMENT: u16 = 0xA000;
+const ATTRIBUTE_SEGMENT: u16 = 0xA200;
+
+/// The attribute bits of a cell.
+pub mod attribute {
+    /// Cleared to hide the character.
+    pub const VISIBLE: u8 = 0x01;
+    pub const BLINK: u8 = 0x02;
+    pub const REVERSE: u8 = 0x04;
+    pub const UNDERLINE: u8 = 0x08;
+
+    pub const BLACK: u8 = 0x00;
+    pub const BLUE: u8 = 0x20;
+    pub const RED: u8 = 0x40;
+    pub const MAGENTA: u8 = 0x60;
+    pub const GREEN: u8 = 0x80;
+    pub const CYAN: u8 = 0xA0;
+    pub const YELLOW: u8 = 0xC0;
+    pub const WHITE: u8 = 0xE0;
+}
+
+/// Writes text straight to text VRAM, bypassing DOS and the BIOS.
+pub struct TextConsole {
+    column: u8,
+    row: u8,
+    attribute: u8,
+    /// The lead byte of a Shift-JIS character split across two writes.
+    pending_lead: Option<u8>,
+}
+
+impl TextConsole {
+    pub const fn new() -> Self {
+        TextConsole {
+            column: 0,
+            row: 0,
+            attribute: attribute::WHITE | attribute::VISIBLE,
+            pending_lead: None,
+        }
+    }
+
+    /// Sets the attribute used for the text written from now on.
+    pub fn set_attribute(&mut self, attribute: u8) {
+        self.attribute = attribute;
+    }
+
+    /// Fills the screen with spaces in the current attribute and moves the
+    /// cursor to the top left.
+    pub fn clear(&mut self) {
+        for cell in 0..COLUMNS as u16 * ROWS as u16 {
+            put_cell(cell, 0x0020, self.attribute);
+        }
+        self.move_to(0, 0);
+    }
+
+    /// Moves the cursor, clamping it to the screen.
+    pub fn move_to(&mut self, column: u8, row: u8) {
+        self.column = column.min(COLUMNS - 1);
+        self.row = row.min(ROWS - 1);
+        self.update_cursor();
+    }
+
+    pub fn position(&self) -> (u8, u8) {
+        (self.column, self.row)
+    }
+
+    /// Shows or hides the blinking cursor.
+    pub fn set_cursor_visible(&self, visible: bool) {
+        // The standard 16 line character rows, with the cursor at the bottom.
+        gdc::TEXT.set_cursor_form(visible, 16, 14, 15);
+    }
+
+    /// Writes Shift-JIS text, handling carriage returns and line feeds, and
+    /// scrolling when the cursor goes past the bottom.
+    ///
+    /// A character may be split across two calls. Bytes that aren't valid
+    /// Shift-JIS are shown as-is, as half-width characters.
+    pub fn write_sjis(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if let Some(lead) = self.pending_lead.take() {
+                if sjis::is_trail_byte(byte) {
+                    let (row, cell) = sjis::to_jis(lead, byte);
+                    self.put_jis(row, cell);
+                    continue;
+                }
+                // The lead byte is shown on its own, and the byte after it is
+                // handled as if it came first, so a line break still works.
+                self.put_half_width(lead);
+            }
+            if sjis::is_lead_byte(byte) {
+                self.pending_lead = Some(byte);
+            } else {
+                match byte {
+                    b'\r' => self.column = 0,
+                    b'\n' => self.new_line(),
+                    // Half-width katakana share their Shift-JIS codes.
+                    _ => self.put_half_width(byte),
+                }
+            }
+        }
+        self.update_cursor();
+    }
+
+    /// Writes a single-cell character at the cursor and advances it.
+    fn put_half_width(&mut self, code: u8) {
+        put_cell(self.cell(), code as u16, self.attribute);
+        self.advance(1);
+    }
+
+    /// Writes a JIS X 0208 character at the cursor and advances it.
+    fn put_jis(&mut self, row: u8, cell: u8) {
+        let code = (cell as u16) << 8 | (row - 0x20) as u16;
+        // Rows 9 to 11 of the PC-98 font hold half-width symbols that only
+        // take up a single cell.
+        if (0x29..=0x2B).contains(&row) {
+            put_cell(self.cell(), code, self.attribute);
+            self.advance(1);
+            return;
+        }
+        // A full-width character can't be split across lines.
+        if self.column == COLUMNS - 1 {
+            self.new_line();
+        }
+        put_cell(self.cell(), code, self.attribute);
+        put_cell(self.cell() + 1, code | 0x80, self.attribute);
+        self.advance(2);
+    }
+
+    fn cell(&self) -> u16 {
+        self.row as u16 * COLUMNS as u16 + self.column as u16
+    }
+
+    fn advance(&mut self, cells: u8) {
+        self.column += cells;
+        if self.column >= COLUMNS {
+            self.new_line();
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row == ROWS - 1 {
+            self.scroll();
+        } else {
+            self.row += 1;
+        }
+    }
+
+    /// Moves every line up by one and clears the bottom line.
+    fn scroll(&mut self) {
+        let cells = COLUMNS as usize * (ROWS as usize - 1);
+        for &segment in &[TEXT_SEGMENT, ATTRIBUTE_SEGMENT] {
+            let segment = Segment::new(segment);
+            let below = segment.slice::<u16>(COLUMNS as u16 * 2, cells);
+            segment.slice::<u16>(0, cells).copy_from(&below);
+        }
+        let bottom = (ROWS as u16 - 1) * COLUMNS as u16;
+        for cell in bottom..bottom + COLUMNS as u16 {
+            put_cell(cell, 0x0020, self.attribute);
+        }
+    }
+
+    fn update_cursor(&self) {
+        gdc::TEXT.set_cursor(self.cell() as u32);
+    }
+}
+
+/// Writes a character code and attribute to a cell, counted from the top left.
+pub fn put_cell(cell: u16, code: u16, attribute: u8) {
+    Segment::new(TEXT_SEGMENT).ptr(cell * 2).write(code);
+    Segment::new(ATTRIBUTE_SEGMENT).ptr(cell * 2).write(attribute);
+}
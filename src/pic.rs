@@ -0,0 +1,172 @@
+//! Intel 8259 programmable interrupt controller management
+//!
+//! Both the IBM PC/AT and the PC-98 use a master and a slave 8259, but they
+//! sit at different ports, the slave is cascaded through a different master
+//! line, and the BIOSes map them to different interrupt vectors. The layout in
+//! use is selected once at startup with [`select`] and defaults to the IBM
+//! one. IRQ numbers are 0-7 for the master and 8-15 for the slave on both.
+
+#![allow(dead_code)]
+
+use core::ptr::{addr_of, read_volatile};
+use crate::port::{inb, outb};
+
+/// Where a machine's PICs live and how they are wired.
+pub struct Layout {
+    pub master_command: u16,
+    pub master_data: u16,
+    pub slave_command: u16,
+    pub slave_data: u16,
+    /// The interrupt vector of IRQ 0.
+    pub master_base: u8,
+    /// The interrupt vector of IRQ 8.
+    pub slave_base: u8,
+    /// The master IRQ that the slave is cascaded through.
+    pub cascade: u8,
+}
+
+/// The IBM PC/AT layout.
+pub const IBM: Layout = Layout {
+    master_command: 0x20,
+    master_data: 0x21,
+    slave_command: 0xA0,
+    slave_data: 0xA1,
+    master_base: 0x08,
+    slave_base: 0x70,
+    cascade: 2,
+};
+
+/// The NEC PC-98 layout.
+pub const PC98: Layout = Layout {
+    master_command: 0x00,
+    master_data: 0x02,
+    slave_command: 0x08,
+    slave_data: 0x0A,
+    master_base: 0x08,
+    slave_base: 0x10,
+    cascade: 7,
+};
+
+static mut LAYOUT: &Layout = &IBM;
+
+/// The command ports of the selected layout, for interrupt handlers written
+/// in assembly to send their EOIs to.
+#[doc(hidden)]
+pub static mut MASTER_COMMAND_PORT: u16 = IBM.master_command;
+#[doc(hidden)]
+pub static mut SLAVE_COMMAND_PORT: u16 = IBM.slave_command;
+
+const NON_SPECIFIC_EOI: u8 = 0x20;
+const SPECIFIC_EOI: u8 = 0x60;
+const READ_IRR: u8 = 0x0A;
+const READ_ISR: u8 = 0x0B;
+
+/// Chooses the layout that the rest of this module uses.
+pub fn select(layout: &'static Layout) {
+    unsafe {
+        LAYOUT = layout;
+        MASTER_COMMAND_PORT = layout.master_command;
+        SLAVE_COMMAND_PORT = layout.slave_command;
+    }
+}
+
+/// Returns the layout in use.
+pub fn layout() -> &'static Layout {
+    unsafe { read_volatile(addr_of!(LAYOUT)) }
+}
+
+/// Returns the interrupt vector that the BIOS maps an IRQ to.
+pub fn irq_vector(irq: u8) -> u8 {
+    let layout = layout();
+    if irq < 8 { layout.master_base + irq } else { layout.slave_base + irq - 8 }
+}
+
+/// Returns the data port and mask bit that control an IRQ.
+fn mask_bit(irq: u8) -> (u16, u8) {
+    let layout = layout();
+    if irq < 8 { (layout.master_data, 1 << irq) } else { (layout.slave_data, 1 << (irq - 8)) }
+}
+
+/// Returns whether an IRQ is masked.
+pub fn is_masked(irq: u8) -> bool {
+    let (port, bit) = mask_bit(irq);
+    unsafe { inb(port) & bit != 0 }
+}
+
+/// Masks or unmasks an IRQ, returning whether it was masked before.
+///
+/// Unmasking a slave IRQ also unmasks the cascade line on the master.
+pub fn set_masked(irq: u8, masked: bool) -> bool {
+    let (port, bit) = mask_bit(irq);
+    unsafe {
+        let old = inb(port);
+        outb(port, if masked { old | bit } else { old & !bit });
+        if irq >= 8 && !masked {
+            let layout = layout();
+            outb(layout.master_data, inb(layout.master_data) & !(1 << layout.cascade));
+        }
+        old & bit != 0
+    }
+}
+
+pub fn mask(irq: u8) {
+    set_masked(irq, true);
+}
+
+pub fn unmask(irq: u8) {
+    set_masked(irq, false);
+}
+
+/// Sends a non-specific end of interrupt for an IRQ, to the slave as well as
+/// the master if it came from the slave.
+pub fn end_of_interrupt(irq: u8) {
+    let layout = layout();
+    unsafe {
+        if irq >= 8 {
+            outb(layout.slave_command, NON_SPECIFIC_EOI);
+        }
+        outb(layout.master_command, NON_SPECIFIC_EOI);
+    }
+}
+
+/// Sends a specific end of interrupt for an IRQ, which clears that IRQ's
+/// in-service bit even if it isn't the highest priority one.
+pub fn specific_end_of_interrupt(irq: u8) {
+    let layout = layout();
+    unsafe {
+        if irq >= 8 {
+            outb(layout.slave_command, SPECIFIC_EOI | (irq - 8));
+            outb(layout.master_command, SPECIFIC_EOI | layout.cascade);
+        } else {
+            outb(layout.master_command, SPECIFIC_EOI | irq);
+        }
+    }
+}
+
+/// Reads a status register from both PICs, with the slave in the high byte.
+fn read_register(command: u8) -> u16 {
+    let layout = layout();
+    unsafe {
+        outb(layout.master_command, command);
+        outb(layout.slave_command, command);
+        u16::from_le_bytes([inb(layout.master_command), inb(layout.slave_command)])
+    }
+}
+
+/// Reads the interrupt request register: the IRQs that are raised but not yet
+/// being serviced, one bit per IRQ.
+pub fn read_irr() -> u16 {
+    read_register(READ_IRR)
+}
+
+/// Reads the in-service register: the IRQs whose handlers haven't sent an
+/// end of interrupt yet, one bit per IRQ.
+pub fn read_isr() -> u16 {
+    read_register(READ_ISR)
+}
+
+/// Reads the mask registers of both PICs, one bit per IRQ.
+pub fn read_masks() -> u16 {
+    let layout = layout();
+    unsafe { u16::from_le_bytes([inb(layout.master_data), inb(layout.slave_data)]) }
+}
@@ -0,0 +1,186 @@
+//! Programmable interval timer channel 0 and high-resolution timing
+//!
+//! The BIOS runs channel 0 at about 18.2 Hz and keeps the time of day by
+//! counting its interrupts. [`Timer::install`] speeds the channel up and hooks
+//! IRQ 0 with a handler that counts the faster ticks, but still passes on
+//! enough of them to the BIOS handler that it keeps seeing 18.2 Hz, so the
+//! clock, floppy motor timeouts and so on keep working.
+//!
+//! Between ticks, the channel's counter can be latched and read to measure
+//! time with a resolution of less than a microsecond.
+//!
+//! This is for IBM compatibles only. The PC-98's timer sits at different
+//! ports with a different clock, and its port 43h belongs to the keyboard.
+
+#![allow(dead_code)]
+
+use core::ptr::{addr_of, read_volatile};
+use core::ptr::addr_of_mut;
+use crate::interrupts::{self, FarAddress, Handler, VectorGuard};
+use crate::pic;
+use crate::platform::{self, Platform};
+use crate::port::{inb, outb};
+use crate::speaker::PIT_FREQUENCY;
+
+const CHANNEL_0_PORT: u16 = 0x40;
+const COMMAND_PORT: u16 = 0x43;
+
+
+/// The lowest rate that can be programmed, since the divisor is 16 bits.
+pub const MINIMUM_FREQUENCY: u16 = 19;
+
+// State shared with the interrupt handler.
+static mut INSTALLED: bool = false;
+static mut OLD_HANDLER: FarAddress = FarAddress::NULL;
+static mut DIVISOR: u16 = 0;
+/// PIT clocks that have passed since the BIOS handler last ran, modulo
+/// 65536. Overflowing means another 18.2 Hz tick is due.
+static mut BIOS_ACCUMULATOR: u16 = 0;
+static mut TICKS: u32 = 0;
+
+static HANDLER: Handler = unsafe {
+    Handler::from_raw(interrupt_handler, addr_of_mut!(OLD_HANDLER))
+};
+
+/// Channel 0 running at a custom rate.
+///
+/// The original rate and interrupt handler are restored when this is dropped.
+pub struct Timer {
+    frequency: u16,
+    _guard: VectorGuard,
+}
+
+impl Timer {
+    /// Reprograms channel 0 to interrupt at roughly the given frequency, and
+    /// installs the tick counting handler.
+    ///
+    /// Returns `None` if a timer is already installed, or on a PC-98.
+    pub fn install(frequency: u16) -> Option<Self> {
+        if platform::current() != Platform::Ibm || unsafe { read_volatile(addr_of!(INSTALLED)) } {
+            return None;
+        }
+        let frequency = frequency.max(MINIMUM_FREQUENCY);
+        let divisor = (PIT_FREQUENCY / frequency as u32) as u16;
+        let guard = interrupts::without_interrupts(|| unsafe {
+            DIVISOR = divisor;
+            BIOS_ACCUMULATOR = 0;
+            TICKS = 0;
+            let guard = interrupts::hook(pic::irq_vector(0), &HANDLER)?;
+            INSTALLED = true;
+            // Channel 0, low byte then high byte, mode 2 (rate generator),
+            // which counts down by one per clock unlike the BIOS's mode 3.
+            set_divisor(0b0011_0100, divisor);
+            Some(guard)
+        })?;
+        Some(Timer { frequency, _guard: guard })
+    }
+
+    pub fn frequency(&self) -> u16 {
+        self.frequency
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        restore_rate();
+    }
+}
+
+/// Puts channel 0 back to the BIOS rate if a [`Timer`] is installed.
+///
+/// This is for paths that leave the program without dropping the timer, such
+/// as panics, together with [`interrupts::restore_all`]. Otherwise the BIOS
+/// clock would keep running fast after the program exits.
+pub fn restore_rate() {
+    interrupts::without_interrupts(|| unsafe {
+        if read_volatile(addr_of!(INSTALLED)) {
+            // Mode 3 with a divisor of 65536, as the BIOS sets it up.
+            set_divisor(0b0011_0110, 0);
+            INSTALLED = false;
+        }
+    });
+}
+
+/// Returns the number of ticks since the timer was installed.
+pub fn ticks() -> u32 {
+    unsafe { read_volatile(addr_of!(TICKS)) }
+}
+
+/// Returns the time since the timer was installed in microseconds, with the
+/// resolution of the PIT's 1.19 MHz clock.
+pub fn microseconds() -> u64 {
+    clocks() * 838_095 / 1_000_000
+}
+
+/// Returns the number of PIT clocks since the timer was installed.
+pub fn clocks() -> u64 {
+    interrupts::without_interrupts(|| unsafe {
+        let divisor = read_volatile(addr_of!(DIVISOR)) as u64;
+        let mut ticks = read_volatile(addr_of!(TICKS)) as u64;
+        let count = read_count() as u64;
+        // If the counter has wrapped around but the interrupt hasn't been
+        // serviced yet, the tick count is one behind. Read the PIC's
+        // interrupt request register to check.
+        if pic::read_irr() & 0x01 != 0 && count > divisor / 2 {
+            ticks += 1;
+        }
+        ticks * divisor + (divisor - count)
+    })
+}
+
+/// Busy-waits for the given number of microseconds.
+///
+/// This only works while a [`Timer`] is installed.
+pub fn delay_microseconds(microseconds: u32) {
+    let end = self::microseconds() + microseconds as u64;
+    while self::microseconds() < end {}
+}
+
+/// Latches channel 0's counter and reads it.
+unsafe fn read_count() -> u16 {
+    outb(COMMAND_PORT, 0b0000_0000);
+    let low = inb(CHANNEL_0_PORT);
+    let high = inb(CHANNEL_0_PORT);
+    u16::from_le_bytes([low, high])
+}
+
+unsafe fn set_divisor(command: u8, divisor: u16) {
+    outb(COMMAND_PORT, command);
+    outb(CHANNEL_0_PORT, divisor as u8);
+    outb(CHANNEL_0_PORT, (divisor >> 8) as u8);
+}
+
+/// Counts a tick and either chains to the BIOS handler, which sends the EOI
+/// itself, or sends the EOI and returns.
+#[unsafe(naked)]
+unsafe extern "C" fn interrupt_handler() {
+    core::arch::naked_asm!(
+        "push ax",
+        "push ds",
+        // A .COM program's data lives in its code segment.
+        "mov ax, cs",
+        "mov ds, ax",
+        "add dword ptr [{ticks}], 1",
+        "mov ax, word ptr [{divisor}]",
+        "add word ptr [{accumulator}], ax",
+        "jnc 2f",
+        "pop ds",
+        "pop ax",
+        "ljmp dword ptr cs:[{old_handler}]",
+        "2:",
+        // The master PIC's port depends on the platform.
+        "push dx",
+        "mov dx, word ptr [{master_port}]",
+        "mov al, 0x20",
+        "out dx, al",
+        "pop dx",
+        "pop ds",
+        "pop ax",
+        "iret",
+        ticks = sym TICKS,
+        divisor = sym DIVISOR,
+        accumulator = sym BIOS_ACCUMULATOR,
+        old_handler = sym OLD_HANDLER,
+        master_port = sym pic::MASTER_COMMAND_PORT,
+    );
+}
@@ -0,0 +1,95 @@
+//! Runtime detection of the machine the program is running on
+//!
+//! DOS runs on both IBM compatibles and NEC's PC-98, but the two have
+//! almost nothing in common below DOS: different BIOS interrupts, video
+//! hardware, keyboard, timers and interrupt controller ports. Detection looks
+//! for things only one of them has, and [`init`] configures the rest of the
+//! program for the result.
+//!
+//! With only one of the `ibm` and `pc98` features enabled, detection is skipped
+//! and that platform is assumed.
+
+#![allow(dead_code)]
+
+use core::ptr::{addr_of, read_volatile};
+use crate::dos;
+use crate::pic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// An IBM PC/AT compatible.
+    Ibm,
+    /// An NEC PC-9801 or PC-9821, or a compatible such as an Epson.
+    Pc98,
+}
+
+static mut CURRENT: Option<Platform> = None;
+
+/// Detects the platform and sets up the modules that depend on it.
+///
+/// This should be called once at startup, before any hardware is touched.
+pub fn init() -> Platform {
+    let platform = detect();
+    unsafe { CURRENT = Some(platform); }
+    pic::select(match platform {
+        Platform::Ibm => &pic::IBM,
+        Platform::Pc98 => &pic::PC98,
+    });
+    platform
+}
+
+/// Returns the platform the program is running on, detecting it if [`init`]
+/// hasn't been called.
+pub fn current() -> Platform {
+    match unsafe { read_volatile(addr_of!(CURRENT)) } {
+        Some(platform) => platform,
+        None => init(),
+    }
+}
+
+/// Works out which platform the program is running on.
+#[cfg(all(feature = "ibm", not(feature = "pc98")))]
+pub fn detect() -> Platform {
+    Platform::Ibm
+}
+
+/// Works out which platform the program is running on.
+#[cfg(all(feature = "pc98", not(feature = "ibm")))]
+pub fn detect() -> Platform {
+    Platform::Pc98
+}
+
+/// Works out which platform the program is running on.
+///
+/// Neither test is conclusive alone, so the IBM BIOS date is trusted first,
+/// and anything unrecognized is assumed to be an IBM compatible.
+#[cfg(any(all(feature = "ibm", feature = "pc98"), not(any(feature = "ibm", feature = "pc98"))))]
+pub fn detect() -> Platform {
+    if has_ibm_bios_date() {
+        Platform::Ibm
+    } else if has_nec_dos_extensions() {
+        Platform::Pc98
+    } else {
+        Platform::Ibm
+    }
+}
+
+/// IBM compatible BIOSes keep their release date as `MM/DD/YY` at F000:FFF5,
+/// where software has looked for it since the original PC. The PC-98 BIOS
+/// keeps code there.
+fn has_ibm_bios_date() -> bool {
+    (0..8u16).all(|i| {
+        let byte = dos::read_far_byte(0xF000, 0xFFF5 + i);
+        match i {
+            2 | 5 => byte == b'/',
+            _ => byte.is_ascii_digit(),
+        }
+    })
+}
+
+/// The PC-98 versions of MS-DOS provide extra services through INT DCh,
+/// which is left unused on IBM compatibles.
+fn has_nec_dos_extensions() -> bool {
+    let (segment, offset) = dos::get_interrupt_vector(0xDC);
+    segment != 0 || offset != 0
+}
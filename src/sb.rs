@@ -0,0 +1,506 @@
+//! Sound Blaster digital audio playback
+//!
+//! The card's settings come from the `BLASTER` environment variable, which
+//! is set by the card's installation program (e.g. `A220 I5 D1 T3`). Samples
+//! are unsigned 8-bit mono PCM, which the DSP fetches by itself through the
+//! 8237 DMA controller and signals the end of each block with an IRQ.
+//!
+//! One-shot sounds use single-cycle transfers, split wherever the samples
+//! cross a 64 KiB physical boundary. Streaming uses an auto-initialized
+//! transfer over a buffer of two halves: while the DSP plays one half, the
+//! program refills the other.
+
+#![allow(dead_code)]
+
+use core::convert::TryFrom;
+use core::ptr::{addr_of, addr_of_mut, read_volatile};
+use crate::dma;
+use crate::dos;
+use crate::interrupts::{self, FarAddress, Handler, VectorGuard};
+use crate::pic;
+use crate::port::{inb, outb};
+
+const RESET: u16 = 0x6;
+const READ_DATA: u16 = 0xA;
+const WRITE: u16 = 0xC;
+const READ_STATUS: u16 = 0xE;
+
+const SET_TIME_CONSTANT: u8 = 0x40;
+const SET_BLOCK_SIZE: u8 = 0x48;
+const SINGLE_CYCLE_OUTPUT: u8 = 0x14;
+const AUTO_INIT_OUTPUT: u8 = 0x1C;
+const PAUSE: u8 = 0xD0;
+const SPEAKER_ON: u8 = 0xD1;
+const SPEAKER_OFF: u8 = 0xD3;
+const EXIT_AUTO_INIT: u8 = 0xDA;
+const GET_VERSION: u8 = 0xE1;
+
+/// The size of the streaming buffer. Each half is played as one block.
+pub const STREAM_SIZE: usize = 2048;
+
+/// Room for a streaming buffer that doesn't cross a 64 KiB physical
+/// boundary, wherever the area happens to be placed.
+static mut DMA_AREA: [u8; STREAM_SIZE * 2] = [0x80; STREAM_SIZE * 2];
+
+// State shared with the interrupt handler.
+static mut ACK_PORT: u16 = 0;
+static mut SLAVE_PIC: u8 = 0;
+static mut INTERRUPT_COUNT: u16 = 0;
+/// Unused, as the handler never chains.
+static mut PREVIOUS_HANDLER: FarAddress = FarAddress::NULL;
+
+static HANDLER: Handler = unsafe {
+    Handler::from_raw(interrupt_handler, addr_of_mut!(PREVIOUS_HANDLER))
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The `BLASTER` environment variable is not set.
+    NoBlasterVariable,
+    /// The `BLASTER` environment variable lacks an address, IRQ or DMA
+    /// channel, or has one that can't be used.
+    InvalidBlasterVariable,
+    /// Nothing answered a DSP reset at the given address.
+    DspNotFound,
+    /// The DSP is older than version 2.00 and can't auto-initialize.
+    UnsupportedDsp,
+    /// The data is not an 8-bit mono PCM WAV file.
+    InvalidWav,
+    /// Too many other interrupt vectors are hooked to hook the IRQ.
+    TooManyHooks,
+}
+
+/// The card settings found in the `BLASTER` environment variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlasterConfig {
+    /// The base I/O port (`A`), in hexadecimal in the variable.
+    pub base: u16,
+    /// The IRQ line (`I`).
+    pub irq: u8,
+    /// The 8-bit DMA channel (`D`).
+    pub dma: u8,
+    /// The 16-bit DMA channel (`H`), on the SB16.
+    pub high_dma: Option<u8>,
+    /// The card type (`T`).
+    pub card_type: Option<u8>,
+}
+
+impl BlasterConfig {
+    /// Reads the settings from the `BLASTER` environment variable.
+    pub fn from_environment() -> Result<Self, Error> {
+        let mut buffer = [0; 64];
+        let length = dos::environment_variable("BLASTER", &mut buffer)
+            .ok_or(Error::NoBlasterVariable)?;
+        Self::parse(&buffer[..length])
+    }
+
+    /// Parses a `BLASTER` value such as `A220 I5 D1 H5 T6`.
+    pub fn parse(value: &[u8]) -> Result<Self, Error> {
+        let (mut base, mut irq, mut dma) = (None, None, None);
+        let (mut high_dma, mut card_type) = (None, None);
+        for setting in value.split(|&b| b == b' ' || b == b'\t').filter(|s| !s.is_empty()) {
+            let digits = &setting[1..];
+            match setting[0].to_ascii_uppercase() {
+                b'A' => base = Some(parse_number(digits, 16)?),
+                b'I' => irq = Some(parse_byte(digits)?),
+                b'D' => dma = Some(parse_byte(digits)?),
+                b'H' => high_dma = Some(parse_byte(digits)?),
+                b'T' => card_type = Some(parse_byte(digits)?),
+                // Mixer ports, MIDI ports and the like aren't needed.
+                _ => {}
+            }
+        }
+        match (base, irq, dma) {
+            (Some(base), Some(irq), Some(dma))
+                if irq < 16 && dma < 4 && high_dma.is_none_or(|high_dma| high_dma < 8) =>
+            {
+                Ok(BlasterConfig { base, irq, dma, high_dma, card_type })
+            }
+            _ => Err(Error::InvalidBlasterVariable),
+        }
+    }
+}
+
+fn parse_byte(digits: &[u8]) -> Result<u8, Error> {
+    u8::try_from(parse_number(digits, 10)?).map_err(|_| Error::InvalidBlasterVariable)
+}
+
+fn parse_number(digits: &[u8], radix: u16) -> Result<u16, Error> {
+    if digits.is_empty() {
+        return Err(Error::InvalidBlasterVariable);
+    }
+    digits.iter().try_fold(0u16, |value, &digit| {
+        let digit = (digit as char).to_digit(radix as u32).ok_or(Error::InvalidBlasterVariable)?;
+        value.checked_mul(radix)
+            .and_then(|value| value.checked_add(digit as u16))
+            .ok_or(Error::InvalidBlasterVariable)
+    })
+}
+
+/// An unsigned 8-bit mono PCM WAV file.
+pub struct Wav<'a> {
+    pub sample_rate: u32,
+    pub samples: &'a [u8],
+}
+
+impl<'a> Wav<'a> {
+    /// Finds the format and sample data in the chunks of a RIFF WAVE file.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(Error::InvalidWav);
+        }
+        let mut sample_rate = None;
+        let mut chunks = &bytes[12..];
+        while chunks.len() >= 8 {
+            let id = &chunks[0..4];
+            let end = (read_u32(&chunks[4..8]) as usize).checked_add(8).ok_or(Error::InvalidWav)?;
+            let body = chunks.get(8..end).ok_or(Error::InvalidWav)?;
+            match id {
+                b"fmt " => {
+                    if body.len() < 16 {
+                        return Err(Error::InvalidWav);
+                    }
+                    let format = read_u16(&body[0..2]);
+                    let channels = read_u16(&body[2..4]);
+                    let bits = read_u16(&body[14..16]);
+                    if format != 1 || channels != 1 || bits != 8 {
+                        return Err(Error::InvalidWav);
+                    }
+                    sample_rate = Some(read_u32(&body[4..8]));
+                }
+                b"data" => {
+                    let sample_rate = sample_rate.ok_or(Error::InvalidWav)?;
+                    return Ok(Wav { sample_rate, samples: body });
+                }
+                _ => {}
+            }
+            // Chunks are padded to an even length. The end is within the data,
+            // so adding the padding can't overflow.
+            let next = end + (end & 1);
+            chunks = chunks.get(next..).unwrap_or(&[]);
+        }
+        Err(Error::InvalidWav)
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+enum Playback {
+    Idle,
+    /// A single-cycle block is playing, with these samples still to follow.
+    OneShot(&'static [u8]),
+    /// The DSP is looping over the streaming buffer, and this half is the
+    /// next to finish playing.
+    Stream { next_half: usize },
+}
+
+/// A detected and initialized Sound Blaster.
+///
+/// The IRQ handler is installed for as long as this exists, and the original
+/// one is restored when it is dropped.
+pub struct SoundBlaster {
+    config: BlasterConfig,
+    version: (u8, u8),
+    playback: Playback,
+    /// The number of interrupts that have been handled so far.
+    handled: u16,
+    was_masked: bool,
+    _guard: VectorGuard,
+}
+
+impl SoundBlaster {
+    /// Finds the card described by the `BLASTER` environment variable.
+    pub fn from_environment() -> Result<Self, Error> {
+        Self::new(BlasterConfig::from_environment()?)
+    }
+
+    /// Resets the DSP and installs the IRQ handler.
+    pub fn new(config: BlasterConfig) -> Result<Self, Error> {
+        if !reset_dsp(config.base) {
+            return Err(Error::DspNotFound);
+        }
+        write_dsp(config.base, GET_VERSION);
+        let major = read_dsp(config.base).ok_or(Error::DspNotFound)?;
+        let minor = read_dsp(config.base).ok_or(Error::DspNotFound)?;
+        if major < 2 {
+            return Err(Error::UnsupportedDsp);
+        }
+
+        unsafe {
+            ACK_PORT = config.base + READ_STATUS;
+            SLAVE_PIC = (config.irq >= 8) as u8;
+            INTERRUPT_COUNT = 0;
+        }
+        let guard = interrupts::hook(pic::irq_vector(config.irq), &HANDLER)
+            .ok_or(Error::TooManyHooks)?;
+        let was_masked = pic::set_masked(config.irq, false);
+
+        Ok(SoundBlaster {
+            config,
+            version: (major, minor),
+            playback: Playback::Idle,
+            handled: 0,
+            was_masked,
+            _guard: guard,
+        })
+    }
+
+    pub fn config(&self) -> BlasterConfig {
+        self.config
+    }
+
+    /// Returns the DSP's major and minor version numbers.
+    pub fn version(&self) -> (u8, u8) {
+        self.version
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !matches!(self.playback, Playback::Idle)
+    }
+
+    /// Starts playing samples once, replacing whatever was playing before.
+    ///
+    /// [`SoundBlaster::update`] must be called regularly for sounds longer
+    /// than a single DMA block to keep playing.
+    pub fn play(&mut self, samples: &'static [u8], sample_rate: u16) {
+        self.stop();
+        self.start_output(sample_rate);
+        self.playback = Playback::OneShot(samples);
+        self.play_next_block();
+    }
+
+    /// Starts streaming samples, replacing whatever was playing before.
+    ///
+    /// `fill` is called with each half of the buffer that needs samples, first
+    /// for the whole buffer and then from [`SoundBlaster::stream`].
+    pub fn start_stream<F: FnMut(&mut [u8])>(&mut self, sample_rate: u16, mut fill: F) {
+        self.stop();
+        let buffer = stream_buffer();
+        fill(buffer);
+        self.start_output(sample_rate);
+        let half = (STREAM_SIZE / 2 - 1) as u16;
+        unsafe {
+            dma::start(self.config.dma, dma::Mode::AutoInitRead,
+                       dma::physical_address(buffer.as_ptr()), STREAM_SIZE as u16);
+        }
+        self.write(SET_BLOCK_SIZE);
+        self.write(half as u8);
+        self.write((half >> 8) as u8);
+        self.write(AUTO_INIT_OUTPUT);
+        self.playback = Playback::Stream { next_half: 0 };
+    }
+
+    /// Refills every half of the streaming buffer that has finished playing
+    /// since the last call.
+    pub fn stream<F: FnMut(&mut [u8])>(&mut self, mut fill: F) {
+        while self.take_interrupt() {
+            if let Playback::Stream { next_half } = &mut self.playback {
+                let start = *next_half * STREAM_SIZE / 2;
+                fill(&mut stream_buffer()[start..start + STREAM_SIZE / 2]);
+                *next_half ^= 1;
+            }
+        }
+    }
+
+    /// Continues one-shot playback after each block finishes.
+    pub fn update(&mut self) {
+        while self.take_interrupt() {
+            if let Playback::OneShot(_) = self.playback {
+                self.play_next_block();
+            }
+        }
+    }
+
+    /// Stops playback and turns the speaker off.
+    pub fn stop(&mut self) {
+        match self.playback {
+            Playback::Idle => return,
+            Playback::Stream { .. } => self.write(EXIT_AUTO_INIT),
+            Playback::OneShot(_) => {}
+        }
+        self.write(PAUSE);
+        self.write(SPEAKER_OFF);
+        dma::mask(self.config.dma);
+        self.playback = Playback::Idle;
+    }
+
+    fn start_output(&mut self, sample_rate: u16) {
+        let sample_rate = sample_rate.clamp(4000, 22222) as u32;
+        self.write(SET_TIME_CONSTANT);
+        self.write((256 - 1_000_000 / sample_rate) as u8);
+        self.write(SPEAKER_ON);
+    }
+
+    fn play_next_block(&mut self) {
+        let remaining = match self.playback {
+            Playback::OneShot(remaining) => remaining,
+            _ => return,
+        };
+        if remaining.is_empty() {
+            self.stop();
+            return;
+        }
+        let address = dma::physical_address(remaining.as_ptr());
+        let length = (remaining.len() as u32).min(dma::bytes_until_boundary(address)).min(0x8000);
+        unsafe {
+            dma::start(self.config.dma, dma::Mode::SingleCycleRead, address, length as u16);
+        }
+        let count = length as u16 - 1;
+        self.write(SINGLE_CYCLE_OUTPUT);
+        self.write(count as u8);
+        self.write((count >> 8) as u8);
+        self.playback = Playback::OneShot(&remaining[length as usize..]);
+    }
+
+    /// Consumes one interrupt that hasn't been handled yet, if there is one.
+    fn take_interrupt(&mut self) -> bool {
+        let count = unsafe { read_volatile(addr_of!(INTERRUPT_COUNT)) };
+        if count == self.handled {
+            return false;
+        }
+        self.handled = self.handled.wrapping_add(1);
+        true
+    }
+
+    fn write(&self, value: u8) {
+        write_dsp(self.config.base, value);
+    }
+}
+
+impl Drop for SoundBlaster {
+    fn drop(&mut self) {
+        self.stop();
+        pic::set_masked(self.config.irq, self.was_masked);
+    }
+}
+
+/// Returns the half of `DMA_AREA` that doesn't cross a 64 KiB boundary.
+fn stream_buffer() -> &'static mut [u8] {
+    let area = unsafe { &mut *addr_of_mut!(DMA_AREA) };
+    let until_boundary = dma::bytes_until_boundary(dma::physical_address(area.as_ptr())) as usize;
+    let start = if until_boundary >= STREAM_SIZE { 0 } else { until_boundary };
+    &mut area[start..start + STREAM_SIZE]
+}
+
+fn reset_dsp(base: u16) -> bool {
+    unsafe {
+        outb(base + RESET, 1);
+        // The reset line must be held for at least 3 µs.
+        for _ in 0..8 {
+            inb(base + RESET);
+        }
+        outb(base + RESET, 0);
+    }
+    read_dsp(base) == Some(0xAA)
+}
+
+fn write_dsp(base: u16, value: u8) {
+    unsafe {
+        while inb(base + WRITE) & 0x80 != 0 {}
+        outb(base + WRITE, value);
+    }
+}
+
+fn read_dsp(base: u16) -> Option<u8> {
+    for _ in 0..0xFFFFu16 {
+        unsafe {
+            if inb(base + READ_STATUS) & 0x80 != 0 {
+                return Some(inb(base + READ_DATA));
+            }
+        }
+    }
+    None
+}
+
+/// Acknowledges the DSP's interrupt, counts it and sends an EOI to the PICs.
+///
+/// The refilling itself happens outside of the interrupt, in
+/// [`SoundBlaster::stream`] and [`SoundBlaster::update`].
+#[unsafe(naked)]
+unsafe extern "C" fn interrupt_handler() {
+    core::arch::naked_asm!(
+        "push ax",
+        "push dx",
+        "push ds",
+        // A .COM program's data lives in its code segment.
+        "mov ax, cs",
+        "mov ds, ax",
+        // Reading the DSP's status port acknowledges the interrupt.
+        "mov dx, word ptr [{ack_port}]",
+        "in al, dx",
+        "inc word ptr [{count}]",
+        // The PICs' ports depend on the platform.
+        "mov al, 0x20",
+        "cmp byte ptr [{slave}], 0",
+        "je 2f",
+        "mov dx, word ptr [{slave_port}]",
+        "out dx, al",
+        "2:",
+        "mov dx, word ptr [{master_port}]",
+        "out dx, al",
+        "pop ds",
+        "pop dx",
+        "pop ax",
+        "iret",
+        ack_port = sym ACK_PORT,
+        count = sym INTERRUPT_COUNT,
+        slave = sym SLAVE_PIC,
+        master_port = sym pic::MASTER_COMMAND_PORT,
+        slave_port = sym pic::SLAVE_COMMAND_PORT,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_blaster() {
+        let config = BlasterConfig::parse(b"A220 I5 D1 H5 P330 T6").unwrap();
+        assert_eq!(config.base, 0x220);
+        assert_eq!(config.irq, 5);
+        assert_eq!(config.dma, 1);
+        assert_eq!(config.high_dma, Some(5));
+        assert_eq!(config.card_type, Some(6));
+    }
+
+    #[test]
+    fn test_parse_blaster_missing_setting() {
+        assert_eq!(BlasterConfig::parse(b"A220 D1"), Err(Error::InvalidBlasterVariable));
+        assert_eq!(BlasterConfig::parse(b"A2G0 I5 D1"), Err(Error::InvalidBlasterVariable));
+        assert_eq!(BlasterConfig::parse(b"A220 I261 D257"), Err(Error::InvalidBlasterVariable));
+        assert_eq!(BlasterConfig::parse(b"A220 I5 D1 H8"), Err(Error::InvalidBlasterVariable));
+    }
+
+    #[test]
+    fn test_parse_wav() {
+        let mut file = [0u8; 48];
+        file[0..4].copy_from_slice(b"RIFF");
+        file[8..12].copy_from_slice(b"WAVE");
+        file[12..16].copy_from_slice(b"fmt ");
+        file[16..20].copy_from_slice(&16u32.to_le_bytes());
+        file[20..22].copy_from_slice(&1u16.to_le_bytes());
+        file[22..24].copy_from_slice(&1u16.to_le_bytes());
+        file[24..28].copy_from_slice(&11025u32.to_le_bytes());
+        file[34..36].copy_from_slice(&8u16.to_le_bytes());
+        file[36..40].copy_from_slice(b"data");
+        file[40..44].copy_from_slice(&4u32.to_le_bytes());
+        file[44..48].copy_from_slice(&[0x80, 0x90, 0x70, 0x80]);
+
+        let wav = Wav::parse(&file).unwrap();
+        assert_eq!(wav.sample_rate, 11025);
+        assert_eq!(wav.samples, &[0x80, 0x90, 0x70, 0x80]);
+
+        file[34] = 16;
+        assert!(Wav::parse(&file).is_err());
+
+        file[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(Wav::parse(&file).err(), Some(Error::InvalidWav));
+    }
+}
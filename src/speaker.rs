@@ -0,0 +1,145 @@
+//! PC speaker sound through channel 2 of the programmable interval timer
+//!
+//! Channel 2 of the 8253/8254 PIT is wired to the speaker on every IBM
+//! compatible. Programming it as a square wave generator and opening the
+//! speaker gate on port 61h produces a tone until the gate is closed again,
+//! without any further involvement from the CPU.
+
+#![allow(dead_code)]
+
+use crate::port::{inb, outb};
+
+/// The frequency of the clock driving the PIT, in Hz.
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL_2_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+const SPEAKER_PORT: u16 = 0x61;
+
+/// Bit 0 of port 61h gates the PIT clock into channel 2, and bit 1 connects
+/// the channel's output to the speaker.
+const SPEAKER_GATE: u8 = 0b0000_0011;
+
+/// Plays a square wave at the given frequency until [`silence`] is called.
+///
+/// Frequencies below 19 Hz cannot be produced and are clamped.
+pub fn tone(frequency: u16) {
+    if frequency == 0 {
+        silence();
+        return;
+    }
+    let divisor = PIT_FREQUENCY / frequency as u32;
+    let divisor = if divisor > 0xFFFF { 0xFFFF } else { divisor as u16 };
+    unsafe {
+        // Channel 2, low byte then high byte, mode 3 (square wave), binary.
+        outb(COMMAND_PORT, 0b1011_0110);
+        outb(CHANNEL_2_PORT, divisor as u8);
+        outb(CHANNEL_2_PORT, (divisor >> 8) as u8);
+        outb(SPEAKER_PORT, inb(SPEAKER_PORT) | SPEAKER_GATE);
+    }
+}
+
+/// Turns the speaker off.
+pub fn silence() {
+    unsafe {
+        outb(SPEAKER_PORT, inb(SPEAKER_PORT) & !SPEAKER_GATE);
+    }
+}
+
+/// A sound effect played by a [`Sequencer`]. Durations are in timer ticks.
+#[derive(Clone, Copy)]
+pub enum Effect {
+    /// A single steady tone.
+    Tone { frequency: u16, ticks: u16 },
+    /// A linear slide from one frequency to another.
+    Sweep { from: u16, to: u16, ticks: u16 },
+    /// Cycles through a set of frequencies, holding each for `ticks_per_note`,
+    /// `repeats` times over.
+    Arpeggio { frequencies: &'static [u16], ticks_per_note: u16, repeats: u16 },
+}
+
+impl Effect {
+    fn length(&self) -> u32 {
+        match *self {
+            Effect::Tone { ticks, .. } | Effect::Sweep { ticks, .. } => ticks as u32,
+            Effect::Arpeggio { frequencies, ticks_per_note, repeats } => {
+                frequencies.len() as u32 * ticks_per_note as u32 * repeats as u32
+            }
+        }
+    }
+
+    /// Works out the frequency to play on the given tick of the effect.
+    fn frequency_at(&self, tick: u32) -> u16 {
+        match *self {
+            Effect::Tone { frequency, .. } => frequency,
+            Effect::Sweep { from, to, ticks } => {
+                if ticks <= 1 {
+                    return to;
+                }
+                // The span times the tick can reach 2^32, so it is worked out
+                // in 64 bits.
+                let span = to as i64 - from as i64;
+                (from as i64 + span * tick as i64 / (ticks as i64 - 1)) as u16
+            }
+            Effect::Arpeggio { frequencies, ticks_per_note, .. } => {
+                let step = tick / ticks_per_note.max(1) as u32;
+                frequencies[step as usize % frequencies.len()]
+            }
+        }
+    }
+}
+
+/// Plays sound effects in the background.
+///
+/// Nothing happens until [`Sequencer::tick`] is called, which should be done
+/// at a steady rate, such as from a timer interrupt or once per frame. Each
+/// call advances the current effect by one step, so the effect's durations are
+/// measured in whatever rate the caller ticks at.
+pub struct Sequencer {
+    effect: Option<Effect>,
+    tick: u32,
+    frequency: u16,
+}
+
+impl Sequencer {
+    pub const fn new() -> Self {
+        Sequencer { effect: None, tick: 0, frequency: 0 }
+    }
+
+    /// Starts an effect, replacing whatever was playing before.
+    pub fn play(&mut self, effect: Effect) {
+        self.effect = Some(effect);
+        self.tick = 0;
+    }
+
+    /// Stops the current effect and silences the speaker.
+    pub fn stop(&mut self) {
+        self.effect = None;
+        self.frequency = 0;
+        silence();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.effect.is_some()
+    }
+
+    /// Advances the current effect by one tick.
+    pub fn tick(&mut self) {
+        let effect = match self.effect {
+            Some(effect) => effect,
+            None => return,
+        };
+        if self.tick >= effect.length() {
+            self.stop();
+            return;
+        }
+        // Only reprogram the timer when the pitch changes, so that steady
+        // tones don't click.
+        let frequency = effect.frequency_at(self.tick);
+        if frequency != self.frequency {
+            tone(frequency);
+            self.frequency = frequency;
+        }
+        self.tick += 1;
+    }
+}
@@ -0,0 +1,45 @@
+//! Code page 437 shortcuts, for text that is always in the hardware font's
+//! code page regardless of the active one.
+
+use core::iter::Copied;
+use core::slice;
+use super::{Codepage, Decode, Display, CP437};
+
+/// Encodes a character, or returns `None` if it isn't in code page 437.
+///
+/// ASCII control characters are passed through, and the symbols that code
+/// page 437 shows for them encode to the same bytes.
+pub fn encode_char(character: char) -> Option<u8> {
+    CP437.encode_char(character)
+}
+
+pub fn encode_char_lossy(character: char) -> u8 {
+    CP437.encode_char_lossy(character)
+}
+
+/// Decodes a byte as text, with bytes below 80h as ASCII, including the
+/// control characters.
+pub fn decode_byte(byte: u8) -> char {
+    CP437.decode_byte(byte)
+}
+
+/// Decodes a byte as the symbol the hardware font shows for it, which differs
+/// from [`decode_byte`] for the control characters.
+pub fn decode_glyph(byte: u8) -> char {
+    CP437.decode_glyph(byte)
+}
+
+/// Decodes a byte slice.
+pub fn decode(bytes: &[u8]) -> Decode<'static, Copied<slice::Iter<'_, u8>>> {
+    super::decode(&CP437, bytes)
+}
+
+/// Decodes bytes from any iterator, such as one reading a file.
+pub fn decode_iter<I: IntoIterator<Item = u8>>(bytes: I) -> Decode<'static, I::IntoIter> {
+    super::decode_iter(&CP437, bytes)
+}
+
+/// Wraps bytes so that they can be formatted with `{}`.
+pub fn display(bytes: &[u8]) -> Display<'static, '_> {
+    super::display(&CP437, bytes)
+}
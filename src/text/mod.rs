@@ -0,0 +1,312 @@
+//! Text encodings
+//!
+//! Rust strings are UTF-8, while DOS and the hardware fonts use a single-byte
+//! OEM code page, or Shift-JIS on the PC-98. Single-byte code pages implement
+//! [`Codepage`], and the one that [`crate::io`] writes through is chosen at
+//! startup by [`init`] from the code page DOS reports. Characters that it
+//! lacks are shown as ■, or approximated if [`set_transliteration`] is on.
+
+#![allow(dead_code)]
+
+use core::fmt::{self, Write};
+use core::iter::Copied;
+use core::ptr::{addr_of, read_volatile};
+use core::slice;
+use crate::dos;
+
+mod oem;
+pub mod cp437;
+pub mod sjis;
+pub mod transliterate;
+
+pub use oem::{OemCodepage, CP437, CP850, CP852, CP866};
+
+/// A single-byte code page.
+pub trait Codepage {
+    /// The number DOS knows the code page by.
+    fn number(&self) -> u16;
+
+    /// Decodes a byte as text.
+    fn decode_byte(&self, byte: u8) -> char;
+
+    /// Decodes a byte as the symbol the hardware font shows for it, which
+    /// can differ from [`Codepage::decode_byte`] for control characters.
+    fn decode_glyph(&self, byte: u8) -> char {
+        self.decode_byte(byte)
+    }
+
+    /// Encodes a character, or returns `None` if the code page doesn't have
+    /// it.
+    fn encode_char(&self, character: char) -> Option<u8>;
+
+    /// Encodes a character, using ■ if the code page doesn't have it.
+    fn encode_char_lossy(&self, character: char) -> u8 {
+        // ■ is at FEh in all of the supported code pages.
+        self.encode_char(character).unwrap_or(0xFE)
+    }
+}
+
+/// Every supported code page.
+pub static CODEPAGES: [&OemCodepage; 4] = [&CP437, &CP850, &CP852, &CP866];
+
+static mut ACTIVE: &dyn Codepage = &CP437;
+static mut TRANSLITERATION: bool = false;
+
+/// Selects the code page that DOS reports as active, if it is supported.
+/// Otherwise code page 437 stays active.
+pub fn init() {
+    if let Some(codepage) = dos::get_code_page().and_then(by_number) {
+        set_active(codepage);
+    }
+}
+
+/// Looks up a supported code page by its number.
+pub fn by_number(number: u16) -> Option<&'static OemCodepage> {
+    CODEPAGES.iter().copied().find(|codepage| codepage.number() == number)
+}
+
+/// Returns the code page that text is written in.
+pub fn active() -> &'static dyn Codepage {
+    unsafe { read_volatile(addr_of!(ACTIVE)) }
+}
+
+/// Chooses the code page that text is written in.
+pub fn set_active(codepage: &'static dyn Codepage) {
+    unsafe { ACTIVE = codepage; }
+}
+
+/// Returns whether written text goes through [`transliterate::transliterate`].
+pub fn transliteration() -> bool {
+    unsafe { read_volatile(addr_of!(TRANSLITERATION)) }
+}
+
+/// Turns the transliteration fallback for written text on or off. It is off
+/// by default, as it makes writing slower and the binary bigger.
+pub fn set_transliteration(enabled: bool) {
+    unsafe { TRANSLITERATION = enabled; }
+}
+
+/// An iterator that decodes bytes into characters with
+/// [`Codepage::decode_byte`].
+#[derive(Clone)]
+pub struct Decode<'c, I> {
+    codepage: &'c dyn Codepage,
+    bytes: I,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Decode<'_, I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let codepage = self.codepage;
+        self.bytes.next().map(|byte| codepage.decode_byte(byte))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.bytes.size_hint()
+    }
+}
+
+impl<I: DoubleEndedIterator<Item = u8>> DoubleEndedIterator for Decode<'_, I> {
+    fn next_back(&mut self) -> Option<char> {
+        let codepage = self.codepage;
+        self.bytes.next_back().map(|byte| codepage.decode_byte(byte))
+    }
+}
+
+impl<I: ExactSizeIterator<Item = u8>> ExactSizeIterator for Decode<'_, I> {}
+
+/// Decodes a byte slice.
+pub fn decode<'c, 'b>(codepage: &'c dyn Codepage, bytes: &'b [u8]) -> Decode<'c, Copied<slice::Iter<'b, u8>>> {
+    Decode { codepage, bytes: bytes.iter().copied() }
+}
+
+/// Decodes bytes from any iterator, such as one reading a file.
+pub fn decode_iter<I: IntoIterator<Item = u8>>(codepage: &dyn Codepage, bytes: I) -> Decode<'_, I::IntoIter> {
+    Decode { codepage, bytes: bytes.into_iter() }
+}
+
+/// Decodes bytes into a buffer as UTF-8, stopping before the first character
+/// that doesn't fit.
+pub fn decode_into<'o>(codepage: &dyn Codepage, bytes: &[u8], output: &'o mut [u8]) -> &'o str {
+    let mut length = 0;
+    for character in decode(codepage, bytes) {
+        let end = length + character.len_utf8();
+        if end > output.len() {
+            break;
+        }
+        character.encode_utf8(&mut output[length..end]);
+        length = end;
+    }
+    // Only whole characters were written.
+    core::str::from_utf8(&output[..length]).unwrap_or("")
+}
+
+/// Formats bytes in a code page as text.
+#[derive(Clone, Copy)]
+pub struct Display<'c, 'b> {
+    codepage: &'c dyn Codepage,
+    bytes: &'b [u8],
+}
+
+impl fmt::Display for Display<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        decode(self.codepage, self.bytes).try_for_each(|character| f.write_char(character))
+    }
+}
+
+/// Wraps bytes so that they can be formatted with `{}`.
+pub fn display<'c, 'b>(codepage: &'c dyn Codepage, bytes: &'b [u8]) -> Display<'c, 'b> {
+    Display { codepage, bytes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::{self, Write};
+
+    /// Collects formatted text without allocating.
+    struct Buffer {
+        bytes: [u8; 64],
+        length: usize,
+    }
+
+    impl Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.length + s.len();
+            self.bytes.get_mut(self.length..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.length = end;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cp437_round_trip() {
+        for byte in 0..=0xFF {
+            assert_eq!(cp437::encode_char(cp437::decode_byte(byte)), Some(byte));
+            assert_eq!(cp437::encode_char(cp437::decode_glyph(byte)), Some(byte));
+        }
+    }
+
+    #[test]
+    fn test_cp437_decode() {
+        let bytes = b"Gr\x81\xE1e\r\n\x01";
+        let mut decoded = cp437::decode(bytes);
+        for &expected in &['G', 'r', 'ü', 'ß', 'e', '\r', '\n', '\u{1}'] {
+            assert_eq!(decoded.next(), Some(expected));
+        }
+        assert_eq!(decoded.next(), None);
+        assert_eq!(cp437::decode_iter(bytes.iter().copied()).rev().next(), Some('\u{1}'));
+
+        let mut buffer = Buffer { bytes: [0; 64], length: 0 };
+        write!(buffer, "{}", cp437::display(b"\xC9\xCD\xBB 25\xF8")).unwrap();
+        assert_eq!(&buffer.bytes[..buffer.length], "╔═╗ 25°".as_bytes());
+    }
+
+    #[test]
+    fn test_codepages_round_trip() {
+        for codepage in CODEPAGES.iter() {
+            for byte in 0..=0xFF {
+                assert_eq!(codepage.encode_char(codepage.decode_byte(byte)), Some(byte));
+                // Control character symbols that are also in the upper half
+                // encode to the upper half.
+                let glyph = codepage.decode_glyph(byte);
+                let encoded = codepage.encode_char(glyph).unwrap();
+                assert_eq!(codepage.decode_glyph(encoded), glyph);
+            }
+        }
+    }
+
+    #[test]
+    fn test_codepages() {
+        assert_eq!(CP850.encode_char('Ø'), Some(0x9D));
+        assert_eq!(CP852.encode_char('Ł'), Some(0x9D));
+        assert_eq!(CP866.encode_char('Ж'), Some(0x86));
+        assert_eq!(CP437.encode_char('Ж'), None);
+        assert_eq!(by_number(866).map(|codepage| codepage.number()), Some(866));
+        assert!(by_number(932).is_none());
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let mut output = [0; 4];
+        assert_eq!(decode_into(&CP437, b"a\x81", &mut output), "aü");
+        // The second ü doesn't fit.
+        assert_eq!(decode_into(&CP437, b"\x81\x81", &mut output[..3]), "ü");
+    }
+
+    #[test]
+    fn test_transliterate() {
+        let transliterates = |codepage: &dyn Codepage, text: &str, expected: &[u8]| {
+            transliterate::transliterate(codepage, text).eq(expected.iter().copied())
+        };
+        assert!(transliterates(&CP437, "cafe\u{301}", b"caf\x82"));
+        assert!(transliterates(&CP437, "\u{201C}Hi\u{201D} \u{2014} 5\u{20AC}\u{2026}", b"\"Hi\" -- 5EUR..."));
+        assert!(transliterates(&CP437, "\u{141}\u{F3}d\u{17A}", b"L\xA2dz"));
+        assert!(transliterates(&CP852, "\u{141}\u{F3}d\u{17A}", b"\x9D\xA2d\xAB"));
+        // Stray marks are dropped, and unknown characters are still ■.
+        assert!(transliterates(&CP437, "\u{301}a\u{30F}\u{4E00}", b"a\xFE"));
+    }
+
+    #[test]
+    fn test_cp437_encode() {
+        assert_eq!(cp437::encode_char('☺'), Some(0x01));
+        assert_eq!(cp437::encode_char('\u{A0}'), Some(0xFF));
+        assert_eq!(cp437::encode_char('€'), None);
+        assert_eq!(cp437::encode_char('😀'), None);
+        assert_eq!(cp437::encode_char_lossy('€'), 0xFE);
+    }
+
+    #[test]
+    fn test_sjis_decode() {
+        let bytes = [0x82, 0xA0, b'A', 0xB1, 0x88, 0x9F, 0x87, 0x40];
+        let mut decoded = sjis::decode(&bytes);
+        for &expected in &['あ', 'A', 'ｱ', '亜', '①'] {
+            assert_eq!(decoded.next(), Some(Ok(expected)));
+        }
+        assert_eq!(decoded.next(), None);
+    }
+
+    #[test]
+    fn test_sjis_invalid() {
+        // A lead byte followed by a byte that can't be a trail byte leaves
+        // that byte to be decoded on its own.
+        let bytes = [0x82, b'\n', 0xFD, 0x85, 0x40];
+        let mut decoded = sjis::decode(&bytes);
+        assert_eq!(decoded.next(), Some(Err(sjis::Error::InvalidSequence(0))));
+        assert_eq!(decoded.next(), Some(Ok('\n')));
+        assert_eq!(decoded.next(), Some(Err(sjis::Error::InvalidSequence(2))));
+        // Row 9 is unassigned.
+        assert_eq!(decoded.next(), Some(Err(sjis::Error::InvalidSequence(3))));
+        assert_eq!(decoded.next(), None);
+    }
+
+    #[test]
+    fn test_sjis_round_trip() {
+        for lead in (0x81..=0x9F).chain(0xE0..=0xEA) {
+            for trail in (0x40..=0x7E).chain(0x80..=0xFC) {
+                if let Some(character) = sjis::decode_pair(lead, trail) {
+                    let mut buffer = [0; 2];
+                    let encoded = sjis::encode_char(character, &mut buffer).unwrap();
+                    // Characters in both row 2 and NEC's row 13 encode to
+                    // row 2.
+                    assert_eq!(sjis::decode(encoded).next(), Some(Ok(character)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sjis_encode() {
+        let mut output = [0; 8];
+        assert_eq!(sjis::encode("日本ｶ", &mut output), Ok(5));
+        assert_eq!(&output[..5], &[0x93, 0xFA, 0x96, 0x7B, 0xB6]);
+        assert_eq!(sjis::encode("é", &mut output), Err(sjis::Error::Unencodable('é')));
+        assert_eq!(sjis::encode_lossy("é!", &mut output), Ok(3));
+        assert_eq!(&output[..3], &[0x81, 0xAC, b'!']);
+        assert_eq!(sjis::encode("日本語です", &mut output), Err(sjis::Error::BufferTooSmall));
+        // ∵ is in both row 2 and NEC's row 13.
+        let mut buffer = [0; 2];
+        assert_eq!(sjis::encode_char('∵', &mut buffer), Some(&[0x81, 0xE6][..]));
+    }
+}
@@ -0,0 +1,154 @@
+//! Single-byte OEM code pages
+//!
+//! These all share ASCII in their lower half, so only the upper half is
+//! stored. Encoding binary searches a table of every non-ASCII character,
+//! sorted by code point when it is built at compile time.
+
+use super::Codepage;
+
+include!("cp437_glyphs.rs");
+
+/// The most entries an encoding table can have: the control glyphs, the
+/// delete glyph and the upper half.
+const ENCODE_TABLE_LENGTH: usize = 31 + 1 + 128;
+
+/// A code page with ASCII in its lower half.
+pub struct OemCodepage {
+    number: u16,
+    /// The characters for bytes 80h-FFh.
+    upper: [char; 128],
+    /// Every character outside of ASCII as pairs of code point and byte,
+    /// sorted by code point.
+    encode_table: [(u16, u8); ENCODE_TABLE_LENGTH],
+    /// The number of entries in use, as characters that appear twice are
+    /// only entered once.
+    encode_length: usize,
+}
+
+impl OemCodepage {
+    /// Builds a code page and its encoding table from its upper half.
+    ///
+    /// Every character must be in the Basic Multilingual Plane.
+    const fn new(number: u16, upper: [char; 128]) -> Self {
+        let mut codepage = OemCodepage {
+            number,
+            upper,
+            encode_table: [(0, 0); ENCODE_TABLE_LENGTH],
+            encode_length: 0,
+        };
+        let mut byte = 0x80;
+        while byte <= 0xFF {
+            codepage.add_encoding(upper[byte - 0x80], byte as u8);
+            byte += 1;
+        }
+        // Some code pages also have symbols from the control characters in
+        // their upper half, and those bytes are the better encoding as they
+        // are text rather than control characters.
+        let mut byte = 0x01;
+        while byte <= 0x1F {
+            codepage.add_encoding(CONTROL_GLYPHS[byte - 0x01], byte as u8);
+            byte += 1;
+        }
+        codepage.add_encoding(DELETE_GLYPH, 0x7F);
+        codepage
+    }
+
+    /// Inserts a character into the encoding table, keeping it sorted, unless
+    /// the character is already there.
+    const fn add_encoding(&mut self, character: char, byte: u8) {
+        let code_point = character as u32 as u16;
+        let mut i = self.encode_length;
+        while i > 0 && self.encode_table[i - 1].0 >= code_point {
+            if self.encode_table[i - 1].0 == code_point {
+                return;
+            }
+            i -= 1;
+        }
+        // Insertion sort, as it is simple and only runs at compile time.
+        let mut j = self.encode_length;
+        while j > i {
+            self.encode_table[j] = self.encode_table[j - 1];
+            j -= 1;
+        }
+        self.encode_table[i] = (code_point, byte);
+        self.encode_length += 1;
+    }
+
+    fn encode_table(&self) -> &[(u16, u8)] {
+        &self.encode_table[..self.encode_length]
+    }
+}
+
+impl Codepage for OemCodepage {
+    fn number(&self) -> u16 {
+        self.number
+    }
+
+    fn decode_byte(&self, byte: u8) -> char {
+        if byte < 0x80 { byte as char } else { self.upper[byte as usize - 0x80] }
+    }
+
+    fn decode_glyph(&self, byte: u8) -> char {
+        match byte {
+            0x01..=0x1F => CONTROL_GLYPHS[byte as usize - 0x01],
+            0x7F => DELETE_GLYPH,
+            _ => self.decode_byte(byte),
+        }
+    }
+
+    fn encode_char(&self, character: char) -> Option<u8> {
+        let code_point = character as u32;
+        // Take the fast path for ASCII.
+        if code_point < 0x80 {
+            Some(code_point as u8)
+        // Everything else in these code pages is in the Basic Multilingual
+        // Plane.
+        } else if code_point > 0xFFFF {
+            None
+        } else {
+            let table = self.encode_table();
+            table.binary_search_by_key(&(code_point as u16), |&(c, _)| c)
+                .ok()
+                .map(|index| table[index].1)
+        }
+    }
+}
+
+/// The original IBM PC code page, which is also the hardware font.
+pub static CP437: OemCodepage = OemCodepage::new(437, CP437_UPPER);
+
+/// Multilingual Latin 1, for Western European languages.
+pub static CP850: OemCodepage = OemCodepage::new(850, [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À', '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã', '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î', 'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+    'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ', 'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+    '\u{AD}', '±', '‗', '¾', '¶', '§', '÷', '¸', '°', '¨', '·', '¹', '³', '²', '■', '\u{A0}',
+]);
+
+/// Latin 2, for Central European languages.
+pub static CP852: OemCodepage = OemCodepage::new(852, [
+    'Ç', 'ü', 'é', 'â', 'ä', 'ů', 'ć', 'ç', 'ł', 'ë', 'Ő', 'ő', 'î', 'Ź', 'Ä', 'Ć',
+    'É', 'Ĺ', 'ĺ', 'ô', 'ö', 'Ľ', 'ľ', 'Ś', 'ś', 'Ö', 'Ü', 'Ť', 'ť', 'Ł', '×', 'č',
+    'á', 'í', 'ó', 'ú', 'Ą', 'ą', 'Ž', 'ž', 'Ę', 'ę', '¬', 'ź', 'Č', 'ş', '«', '»',
+    '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'Ě', 'Ş', '╣', '║', '╗', '╝', 'Ż', 'ż', '┐',
+    '└', '┴', '┬', '├', '─', '┼', 'Ă', 'ă', '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+    'đ', 'Đ', 'Ď', 'Ë', 'ď', 'Ň', 'Í', 'Î', 'ě', '┘', '┌', '█', '▄', 'Ţ', 'Ů', '▀',
+    'Ó', 'ß', 'Ô', 'Ń', 'ń', 'ň', 'Š', 'š', 'Ŕ', 'Ú', 'ŕ', 'Ű', 'ý', 'Ý', 'ţ', '´',
+    '\u{AD}', '˝', '˛', 'ˇ', '˘', '§', '÷', '¸', '°', '¨', '˙', 'ű', 'Ř', 'ř', '■', '\u{A0}',
+]);
+
+/// Cyrillic, for Russian and other languages.
+pub static CP866: OemCodepage = OemCodepage::new(866, [
+    'А', 'Б', 'В', 'Г', 'Д', 'Е', 'Ж', 'З', 'И', 'Й', 'К', 'Л', 'М', 'Н', 'О', 'П',
+    'Р', 'С', 'Т', 'У', 'Ф', 'Х', 'Ц', 'Ч', 'Ш', 'Щ', 'Ъ', 'Ы', 'Ь', 'Э', 'Ю', 'Я',
+    'а', 'б', 'в', 'г', 'д', 'е', 'ж', 'з', 'и', 'й', 'к', 'л', 'м', 'н', 'о', 'п',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'р', 'с', 'т', 'у', 'ф', 'х', 'ц', 'ч', 'ш', 'щ', 'ъ', 'ы', 'ь', 'э', 'ю', 'я',
+    'Ё', 'ё', 'Є', 'є', 'Ї', 'ї', 'Ў', 'ў', '°', '∙', '·', '√', '№', '¤', '■', '\u{A0}',
+]);
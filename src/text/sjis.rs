@@ -0,0 +1,198 @@
+//! Shift-JIS, as used by the PC-98 and Japanese DOS.
+//!
+//! Covers ASCII, half-width katakana and JIS X 0208 including NEC's row 13
+//! symbols. The user-defined area and IBM extensions are not supported.
+//!
+//! Decoding is a table lookup. Encoding is a binary search of a second table
+//! holding indices into the first, sorted by the code points they lead to.
+
+include!(concat!(env!("OUT_DIR"), "/jis0208.rs"));
+
+/// The character that unencodable characters are replaced with: 〓, the
+/// geta mark that Japanese software traditionally uses for this.
+pub const REPLACEMENT: [u8; 2] = [0x81, 0xAC];
+
+/// Why text couldn't be converted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The bytes starting at this position aren't valid Shift-JIS.
+    InvalidSequence(usize),
+    /// The character has no Shift-JIS encoding.
+    Unencodable(char),
+    /// The output buffer filled up before all of the text was converted.
+    BufferTooSmall,
+}
+
+/// Returns whether a Shift-JIS byte starts a two-byte character.
+pub fn is_lead_byte(byte: u8) -> bool {
+    matches!(byte, 0x81..=0x9F | 0xE0..=0xFC)
+}
+
+/// Returns whether a byte can follow a lead byte.
+pub fn is_trail_byte(byte: u8) -> bool {
+    matches!(byte, 0x40..=0x7E | 0x80..=0xFC)
+}
+
+/// Converts a two-byte Shift-JIS character to its JIS X 0208 row and
+/// cell bytes (each 21h-7Eh).
+///
+/// Shift-JIS packs two JIS rows into each lead byte, and picks which of
+/// the two by the range of the trail byte.
+pub fn to_jis(lead: u8, trail: u8) -> (u8, u8) {
+    let row_pair = if lead <= 0x9F { lead - 0x71 } else { lead - 0xB1 };
+    let mut row = row_pair * 2 + 1;
+    // Trail bytes skip 7Fh.
+    let trail = if trail > 0x7F { trail - 1 } else { trail };
+    let cell = if trail >= 0x9E {
+        row += 1;
+        trail - 0x7D
+    } else {
+        trail - 0x1F
+    };
+    (row, cell)
+}
+
+/// Converts JIS X 0208 row and cell bytes to Shift-JIS lead and trail
+/// bytes. This is the inverse of [`to_jis`].
+pub fn from_jis(row: u8, cell: u8) -> (u8, u8) {
+    let lead = ((row - 0x21) >> 1) + 0x81;
+    let lead = if lead > 0x9F { lead + 0x40 } else { lead };
+    let trail = if row & 1 != 0 {
+        let trail = cell + 0x1F;
+        if trail >= 0x7F { trail + 1 } else { trail }
+    } else {
+        cell + 0x7E
+    };
+    (lead, trail)
+}
+
+/// Decodes a two-byte character, or returns `None` if it isn't assigned.
+pub fn decode_pair(lead: u8, trail: u8) -> Option<char> {
+    if !is_lead_byte(lead) || !is_trail_byte(trail) {
+        return None;
+    }
+    let (row, cell) = to_jis(lead, trail);
+    let index = (row as usize - 0x21) * 94 + (cell as usize - 0x21);
+    match JIS_X_0208.get(index) {
+        Some(&code_point) if code_point != 0 => core::char::from_u32(code_point as u32),
+        _ => None,
+    }
+}
+
+/// Decodes a single-byte character, or returns `None` if the byte is a
+/// lead byte or unassigned.
+pub fn decode_byte(byte: u8) -> Option<char> {
+    match byte {
+        0x00..=0x7F => Some(byte as char),
+        // Half-width katakana are in the same order in both.
+        0xA1..=0xDF => core::char::from_u32(0xFF61 + (byte - 0xA1) as u32),
+        _ => None,
+    }
+}
+
+/// Iterates over the characters in Shift-JIS text.
+///
+/// Each item is either a character or the position of an invalid
+/// sequence, after which decoding carries on with the next byte.
+pub struct Decode<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Iterator for Decode<'a> {
+    type Item = Result<char, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        let byte = *self.bytes.get(start)?;
+        self.position += 1;
+        if !is_lead_byte(byte) {
+            return Some(decode_byte(byte).ok_or(Error::InvalidSequence(start)));
+        }
+        let trail = match self.bytes.get(self.position) {
+            Some(&trail) if is_trail_byte(trail) => trail,
+            // Leave a byte that can't be a trail byte to be decoded on
+            // its own, so that one bad byte doesn't eat the next
+            // character.
+            _ => return Some(Err(Error::InvalidSequence(start))),
+        };
+        self.position += 1;
+        Some(decode_pair(byte, trail).ok_or(Error::InvalidSequence(start)))
+    }
+}
+
+/// Decodes Shift-JIS text, reporting invalid sequences.
+pub fn decode(bytes: &[u8]) -> Decode<'_> {
+    Decode { bytes, position: 0 }
+}
+
+/// Decodes Shift-JIS text, replacing invalid sequences with U+FFFD.
+pub fn decode_lossy<'a>(bytes: &'a [u8]) -> impl Iterator<Item = char> + 'a {
+    decode(bytes).map(|result| result.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+}
+
+/// Encodes a character into a buffer, returning the one or two bytes
+/// used, or `None` if it has no Shift-JIS encoding.
+pub fn encode_char(character: char, buffer: &mut [u8; 2]) -> Option<&[u8]> {
+    let code_point = character as u32;
+    if code_point < 0x80 {
+        buffer[0] = code_point as u8;
+        return Some(&buffer[..1]);
+    }
+    if (0xFF61..=0xFF9F).contains(&code_point) {
+        buffer[0] = (code_point - 0xFF61) as u8 + 0xA1;
+        return Some(&buffer[..1]);
+    }
+    if code_point > 0xFFFF {
+        return None;
+    }
+    let position = JIS_X_0208_ENCODING
+        .binary_search_by_key(&(code_point as u16), |&index| JIS_X_0208[index as usize])
+        .ok()?;
+    let index = JIS_X_0208_ENCODING[position] as usize;
+    let (lead, trail) = from_jis((index / 94) as u8 + 0x21, (index % 94) as u8 + 0x21);
+    *buffer = [lead, trail];
+    Some(&buffer[..])
+}
+
+/// Encodes a character into a buffer, using [`REPLACEMENT`] if it has no
+/// Shift-JIS encoding.
+pub fn encode_char_lossy(character: char, buffer: &mut [u8; 2]) -> &[u8] {
+    if encode_char(character, buffer).is_none() {
+        *buffer = REPLACEMENT;
+    }
+    let length = if is_lead_byte(buffer[0]) { 2 } else { 1 };
+    &buffer[..length]
+}
+
+/// Encodes a string into a buffer, returning the number of bytes written.
+///
+/// Fails on the first character without a Shift-JIS encoding.
+pub fn encode(text: &str, output: &mut [u8]) -> Result<usize, Error> {
+    encode_with(text, output, |character, buffer| {
+        encode_char(character, buffer).map(|bytes| bytes.len()).ok_or(Error::Unencodable(character))
+    })
+}
+
+/// Encodes a string into a buffer, returning the number of bytes written.
+///
+/// Characters without a Shift-JIS encoding are replaced with
+/// [`REPLACEMENT`].
+pub fn encode_lossy(text: &str, output: &mut [u8]) -> Result<usize, Error> {
+    encode_with(text, output, |character, buffer| Ok(encode_char_lossy(character, buffer).len()))
+}
+
+fn encode_with<F>(text: &str, output: &mut [u8], mut encode: F) -> Result<usize, Error>
+    where F: FnMut(char, &mut [u8; 2]) -> Result<usize, Error>
+{
+    let mut written = 0;
+    for character in text.chars() {
+        let mut buffer = [0; 2];
+        let length = encode(character, &mut buffer)?;
+        output.get_mut(written..written + length)
+            .ok_or(Error::BufferTooSmall)?
+            .copy_from_slice(&buffer[..length]);
+        written += length;
+    }
+    Ok(written)
+}
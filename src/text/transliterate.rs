@@ -0,0 +1,206 @@
+//! Fallbacks for characters that a code page doesn't have
+//!
+//! Rather than showing ■ for anything missing, [`transliterate`] tries, in
+//! order:
+//!
+//! 1. Composing a character with the combining marks after it, so that
+//!    decomposed text such as `e` followed by U+0301 finds `é`.
+//! 2. An ASCII approximation for common punctuation and symbols, such as
+//!    straight quotes for curly ones and `EUR` for `€`.
+//! 3. Dropping the accent from a letter, so that `ő` becomes `o` in code pages
+//!    without it.
+//!
+//! Combining marks that can't be composed are dropped.
+
+use core::iter::Peekable;
+use core::str::Chars;
+use super::Codepage;
+
+/// Precomposed letters as their base letter, combining mark and composition,
+/// sorted by base letter then mark.
+///
+/// This covers Latin-1, Latin Extended-A and the basic Cyrillic block, which
+/// hold every accented letter in the supported code pages.
+const COMPOSITIONS: [(char, char, char); 177] = [
+    ('A', '\u{300}', 'À'), ('A', '\u{301}', 'Á'), ('A', '\u{302}', 'Â'), ('A', '\u{303}', 'Ã'),
+    ('A', '\u{304}', 'Ā'), ('A', '\u{306}', 'Ă'), ('A', '\u{308}', 'Ä'), ('A', '\u{30A}', 'Å'),
+    ('A', '\u{328}', 'Ą'), ('C', '\u{301}', 'Ć'), ('C', '\u{302}', 'Ĉ'), ('C', '\u{307}', 'Ċ'),
+    ('C', '\u{30C}', 'Č'), ('C', '\u{327}', 'Ç'), ('D', '\u{30C}', 'Ď'), ('E', '\u{300}', 'È'),
+    ('E', '\u{301}', 'É'), ('E', '\u{302}', 'Ê'), ('E', '\u{304}', 'Ē'), ('E', '\u{306}', 'Ĕ'),
+    ('E', '\u{307}', 'Ė'), ('E', '\u{308}', 'Ë'), ('E', '\u{30C}', 'Ě'), ('E', '\u{328}', 'Ę'),
+    ('G', '\u{302}', 'Ĝ'), ('G', '\u{306}', 'Ğ'), ('G', '\u{307}', 'Ġ'), ('G', '\u{327}', 'Ģ'),
+    ('H', '\u{302}', 'Ĥ'), ('I', '\u{300}', 'Ì'), ('I', '\u{301}', 'Í'), ('I', '\u{302}', 'Î'),
+    ('I', '\u{303}', 'Ĩ'), ('I', '\u{304}', 'Ī'), ('I', '\u{306}', 'Ĭ'), ('I', '\u{307}', 'İ'),
+    ('I', '\u{308}', 'Ï'), ('I', '\u{328}', 'Į'), ('J', '\u{302}', 'Ĵ'), ('K', '\u{327}', 'Ķ'),
+    ('L', '\u{301}', 'Ĺ'), ('L', '\u{30C}', 'Ľ'), ('L', '\u{327}', 'Ļ'), ('N', '\u{301}', 'Ń'),
+    ('N', '\u{303}', 'Ñ'), ('N', '\u{30C}', 'Ň'), ('N', '\u{327}', 'Ņ'), ('O', '\u{300}', 'Ò'),
+    ('O', '\u{301}', 'Ó'), ('O', '\u{302}', 'Ô'), ('O', '\u{303}', 'Õ'), ('O', '\u{304}', 'Ō'),
+    ('O', '\u{306}', 'Ŏ'), ('O', '\u{308}', 'Ö'), ('O', '\u{30B}', 'Ő'), ('R', '\u{301}', 'Ŕ'),
+    ('R', '\u{30C}', 'Ř'), ('R', '\u{327}', 'Ŗ'), ('S', '\u{301}', 'Ś'), ('S', '\u{302}', 'Ŝ'),
+    ('S', '\u{30C}', 'Š'), ('S', '\u{327}', 'Ş'), ('T', '\u{30C}', 'Ť'), ('T', '\u{327}', 'Ţ'),
+    ('U', '\u{300}', 'Ù'), ('U', '\u{301}', 'Ú'), ('U', '\u{302}', 'Û'), ('U', '\u{303}', 'Ũ'),
+    ('U', '\u{304}', 'Ū'), ('U', '\u{306}', 'Ŭ'), ('U', '\u{308}', 'Ü'), ('U', '\u{30A}', 'Ů'),
+    ('U', '\u{30B}', 'Ű'), ('U', '\u{328}', 'Ų'), ('W', '\u{302}', 'Ŵ'), ('Y', '\u{301}', 'Ý'),
+    ('Y', '\u{302}', 'Ŷ'), ('Y', '\u{308}', 'Ÿ'), ('Z', '\u{301}', 'Ź'), ('Z', '\u{307}', 'Ż'),
+    ('Z', '\u{30C}', 'Ž'), ('a', '\u{300}', 'à'), ('a', '\u{301}', 'á'), ('a', '\u{302}', 'â'),
+    ('a', '\u{303}', 'ã'), ('a', '\u{304}', 'ā'), ('a', '\u{306}', 'ă'), ('a', '\u{308}', 'ä'),
+    ('a', '\u{30A}', 'å'), ('a', '\u{328}', 'ą'), ('c', '\u{301}', 'ć'), ('c', '\u{302}', 'ĉ'),
+    ('c', '\u{307}', 'ċ'), ('c', '\u{30C}', 'č'), ('c', '\u{327}', 'ç'), ('d', '\u{30C}', 'ď'),
+    ('e', '\u{300}', 'è'), ('e', '\u{301}', 'é'), ('e', '\u{302}', 'ê'), ('e', '\u{304}', 'ē'),
+    ('e', '\u{306}', 'ĕ'), ('e', '\u{307}', 'ė'), ('e', '\u{308}', 'ë'), ('e', '\u{30C}', 'ě'),
+    ('e', '\u{328}', 'ę'), ('g', '\u{302}', 'ĝ'), ('g', '\u{306}', 'ğ'), ('g', '\u{307}', 'ġ'),
+    ('g', '\u{327}', 'ģ'), ('h', '\u{302}', 'ĥ'), ('i', '\u{300}', 'ì'), ('i', '\u{301}', 'í'),
+    ('i', '\u{302}', 'î'), ('i', '\u{303}', 'ĩ'), ('i', '\u{304}', 'ī'), ('i', '\u{306}', 'ĭ'),
+    ('i', '\u{308}', 'ï'), ('i', '\u{328}', 'į'), ('j', '\u{302}', 'ĵ'), ('k', '\u{327}', 'ķ'),
+    ('l', '\u{301}', 'ĺ'), ('l', '\u{30C}', 'ľ'), ('l', '\u{327}', 'ļ'), ('n', '\u{301}', 'ń'),
+    ('n', '\u{303}', 'ñ'), ('n', '\u{30C}', 'ň'), ('n', '\u{327}', 'ņ'), ('o', '\u{300}', 'ò'),
+    ('o', '\u{301}', 'ó'), ('o', '\u{302}', 'ô'), ('o', '\u{303}', 'õ'), ('o', '\u{304}', 'ō'),
+    ('o', '\u{306}', 'ŏ'), ('o', '\u{308}', 'ö'), ('o', '\u{30B}', 'ő'), ('r', '\u{301}', 'ŕ'),
+    ('r', '\u{30C}', 'ř'), ('r', '\u{327}', 'ŗ'), ('s', '\u{301}', 'ś'), ('s', '\u{302}', 'ŝ'),
+    ('s', '\u{30C}', 'š'), ('s', '\u{327}', 'ş'), ('t', '\u{30C}', 'ť'), ('t', '\u{327}', 'ţ'),
+    ('u', '\u{300}', 'ù'), ('u', '\u{301}', 'ú'), ('u', '\u{302}', 'û'), ('u', '\u{303}', 'ũ'),
+    ('u', '\u{304}', 'ū'), ('u', '\u{306}', 'ŭ'), ('u', '\u{308}', 'ü'), ('u', '\u{30A}', 'ů'),
+    ('u', '\u{30B}', 'ű'), ('u', '\u{328}', 'ų'), ('w', '\u{302}', 'ŵ'), ('y', '\u{301}', 'ý'),
+    ('y', '\u{302}', 'ŷ'), ('y', '\u{308}', 'ÿ'), ('z', '\u{301}', 'ź'), ('z', '\u{307}', 'ż'),
+    ('z', '\u{30C}', 'ž'), ('І', '\u{308}', 'Ї'), ('Г', '\u{301}', 'Ѓ'), ('Е', '\u{300}', 'Ѐ'),
+    ('Е', '\u{308}', 'Ё'), ('И', '\u{300}', 'Ѝ'), ('И', '\u{306}', 'Й'), ('К', '\u{301}', 'Ќ'),
+    ('У', '\u{306}', 'Ў'), ('г', '\u{301}', 'ѓ'), ('е', '\u{300}', 'ѐ'), ('е', '\u{308}', 'ё'),
+    ('и', '\u{300}', 'ѝ'), ('и', '\u{306}', 'й'), ('к', '\u{301}', 'ќ'), ('у', '\u{306}', 'ў'),
+    ('і', '\u{308}', 'ї'),
+];
+
+/// ASCII approximations, sorted by character.
+const APPROXIMATIONS: [(char, &str); 58] = [
+    ('¦', "|"),
+    ('©', "(C)"),
+    ('®', "(R)"),
+    ('³', "3"),
+    ('´', "'"),
+    ('¸', ","),
+    ('¹', "1"),
+    ('¾', "3/4"),
+    ('Ð', "D"),
+    ('×', "x"),
+    ('Ø', "O"),
+    ('Þ', "Th"),
+    ('ð', "d"),
+    ('ø', "o"),
+    ('þ', "th"),
+    ('Đ', "D"),
+    ('đ', "d"),
+    ('ı', "i"),
+    ('Ł', "L"),
+    ('ł', "l"),
+    ('Œ', "OE"),
+    ('œ', "oe"),
+    ('ˆ', "^"),
+    ('˜', "~"),
+    ('\u{2002}', " "),
+    ('\u{2003}', " "),
+    ('\u{2004}', " "),
+    ('\u{2005}', " "),
+    ('\u{2006}', " "),
+    ('\u{2007}', " "),
+    ('\u{2008}', " "),
+    ('\u{2009}', " "),
+    ('\u{200A}', " "),
+    ('\u{200B}', ""),
+    ('‐', "-"),
+    ('‑', "-"),
+    ('‒', "-"),
+    ('–', "-"),
+    ('—', "--"),
+    ('―', "--"),
+    ('‘', "'"),
+    ('’', "'"),
+    ('‚', ","),
+    ('‛', "'"),
+    ('“', "\""),
+    ('”', "\""),
+    ('„', "\""),
+    ('†', "+"),
+    ('…', "..."),
+    ('‰', "%."),
+    ('′', "'"),
+    ('″', "\""),
+    ('‹', "<"),
+    ('›', ">"),
+    ('€', "EUR"),
+    ('™', "TM"),
+    ('−', "-"),
+    ('\u{FEFF}', ""),
+];
+
+/// Returns whether a character is a combining diacritical mark.
+pub fn is_combining(character: char) -> bool {
+    ('\u{300}'..='\u{36F}').contains(&character)
+}
+
+/// Composes a letter and a combining mark into a single character.
+pub fn compose(base: char, mark: char) -> Option<char> {
+    COMPOSITIONS.binary_search_by_key(&(base, mark), |&(b, m, _)| (b, m))
+        .ok()
+        .map(|index| COMPOSITIONS[index].2)
+}
+
+/// Splits an accented letter into its base letter and combining mark.
+pub fn decompose(character: char) -> Option<(char, char)> {
+    COMPOSITIONS.iter()
+        .find(|&&(_, _, composed)| composed == character)
+        .map(|&(base, mark, _)| (base, mark))
+}
+
+/// Returns an ASCII approximation of a character, which may be empty for
+/// invisible characters.
+pub fn approximate(character: char) -> Option<&'static str> {
+    APPROXIMATIONS.binary_search_by_key(&character, |&(c, _)| c)
+        .ok()
+        .map(|index| APPROXIMATIONS[index].1)
+}
+
+/// An iterator that encodes text with fallbacks, yielding bytes.
+pub struct Transliterate<'c, 's> {
+    codepage: &'c dyn Codepage,
+    characters: Peekable<Chars<'s>>,
+    /// The rest of an approximation that is being written out.
+    pending: &'static [u8],
+}
+
+impl Iterator for Transliterate<'_, '_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        loop {
+            if let Some((&byte, rest)) = self.pending.split_first() {
+                self.pending = rest;
+                return Some(byte);
+            }
+            let mut character = self.characters.next()?;
+            if is_combining(character) {
+                continue;
+            }
+            while let Some(composed) = self.characters.peek().and_then(|&mark| compose(character, mark)) {
+                character = composed;
+                self.characters.next();
+            }
+            if let Some(byte) = self.codepage.encode_char(character) {
+                return Some(byte);
+            }
+            if let Some(approximation) = approximate(character) {
+                // ASCII is the same in every supported code page.
+                self.pending = approximation.as_bytes();
+                continue;
+            }
+            let base = decompose(character).map(|(base, _)| base).unwrap_or(character);
+            return Some(self.codepage.encode_char_lossy(base));
+        }
+    }
+}
+
+/// Encodes a string in a code page, falling back to approximations for
+/// characters that it doesn't have.
+///
+/// Combining marks are only composed with characters in the same string.
+pub fn transliterate<'c, 's>(codepage: &'c dyn Codepage, text: &'s str) -> Transliterate<'c, 's> {
+    Transliterate { codepage, characters: text.chars().peekable(), pending: &[] }
+}
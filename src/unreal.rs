@@ -0,0 +1,225 @@
+//! Unreal mode and flat access to all of memory
+//!
+//! Real mode segment registers have a hidden limit as well as a base, which
+//! real mode itself never changes from 64 KiB. [`enable`] switches to
+//! protected mode just long enough to load DS, ES, FS and GS with a 4 GiB
+//! data segment, then switches straight back. Back in real mode, loading a
+//! segment register only changes its base, so every segment keeps the 4 GiB
+//! limit and 32-bit offsets can reach all of memory, while DOS and BIOS calls
+//! carry on working as before.
+//!
+//! The flat access functions load a segment register with 0 and use the
+//! linear address as the offset. Addresses above 1 MiB need A20 enabled,
+//! which [`enable`] takes care of through [`crate::a20`].
+//!
+//! This is impossible in virtual 8086 mode, which is what DOS runs in under
+//! EMM386 or Windows, as the switch to protected mode is privileged.
+//! Anything that switches to protected mode itself and back, such as an XMS
+//! driver's block moves or INT 15h/87h, may reset the limits, so call
+//! [`enable`] again after using them.
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::mem::{self, MaybeUninit};
+use core::ptr::{addr_of, addr_of_mut, read_volatile};
+use crate::a20;
+use crate::dos;
+use crate::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The CPU is in virtual 8086 mode under a memory manager or Windows.
+    V86Mode,
+    /// None of the ways of enabling A20 worked.
+    A20Unavailable,
+}
+
+/// The value loaded into GDTR, as the CPU expects it.
+#[repr(C, packed)]
+struct Gdtr {
+    limit: u16,
+    base: u32,
+}
+
+/// A null descriptor, then a writable data segment with a base of 0 and a
+/// limit of 4 GiB.
+static GDT: [u64; 2] = [0, 0x00CF_9200_0000_FFFF];
+
+/// The selector of the data segment in [`GDT`].
+const DATA_SELECTOR: u16 = 0x08;
+
+static mut GDTR: Gdtr = Gdtr { limit: 0, base: 0 };
+static mut ENABLED: bool = false;
+
+/// Returns whether the CPU is in virtual 8086 mode.
+///
+/// The protection enable bit of the machine status word can be read in any
+/// mode, and can only be set while running real mode code if that code is
+/// really running in virtual 8086 mode.
+pub fn is_v86_mode() -> bool {
+    let status: u16;
+    unsafe {
+        asm!(
+            "smsw {0:x}",
+            out(reg) status,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    status & 0x0001 != 0
+}
+
+/// Returns whether [`enable`] has succeeded.
+pub fn is_enabled() -> bool {
+    unsafe { read_volatile(addr_of!(ENABLED)) }
+}
+
+/// Enables A20 and gives DS, ES, FS and GS a 4 GiB limit.
+///
+/// This can be called again at any time to restore the limits.
+pub fn enable() -> Result<(), Error> {
+    if is_v86_mode() {
+        return Err(Error::V86Mode);
+    }
+    a20::enable().ok_or(Error::A20Unavailable)?;
+    interrupts::without_interrupts(|| unsafe {
+        GDTR = Gdtr {
+            limit: mem::size_of_val(&GDT) as u16 - 1,
+            base: linear_address(addr_of!(GDT) as usize),
+        };
+        load_limits(addr_of_mut!(GDTR) as u32);
+        ENABLED = true;
+    });
+    Ok(())
+}
+
+/// Enters protected mode, loads the data segment into every data segment
+/// register and returns to real mode, then restores the registers' values.
+///
+/// Interrupts must be disabled, as the real mode interrupt vectors are
+/// meaningless in protected mode.
+unsafe fn load_limits(gdtr: u32) {
+    asm!(
+        "push ds",
+        "push es",
+        "push fs",
+        "push gs",
+        "lgdt [ecx]",
+        "mov eax, cr0",
+        "or al, 1",
+        "mov cr0, eax",
+        // Clear the prefetch queue, as old processors need after switching.
+        "jmp 2f",
+        "2:",
+        "mov ds, dx",
+        "mov es, dx",
+        "mov fs, dx",
+        "mov gs, dx",
+        "and al, 0xFE",
+        "mov cr0, eax",
+        "jmp 3f",
+        "3:",
+        "pop gs",
+        "pop fs",
+        "pop es",
+        "pop ds",
+        in("ecx") gdtr,
+        in("dx") DATA_SELECTOR,
+        out("eax") _,
+    );
+}
+
+/// Converts a near pointer into the program's segment into a linear address.
+pub fn linear_address(pointer: usize) -> u32 {
+    ((dos::code_segment() as u32) << 4) + pointer as u32
+}
+
+/// Reads a value from a linear address.
+///
+/// # Panics
+///
+/// If unreal mode isn't enabled.
+pub fn read<T: Copy>(address: u32) -> T {
+    let mut value = MaybeUninit::<T>::uninit();
+    copy(address, linear_address(value.as_mut_ptr() as usize), mem::size_of::<T>() as u32);
+    unsafe { value.assume_init() }
+}
+
+/// Writes a value to a linear address.
+///
+/// # Panics
+///
+/// If unreal mode isn't enabled.
+pub fn write<T: Copy>(address: u32, value: T) {
+    copy(linear_address(&value as *const T as usize), address, mem::size_of::<T>() as u32);
+}
+
+/// Copies bytes from a linear address into a buffer, filling all of it.
+///
+/// # Panics
+///
+/// If unreal mode isn't enabled.
+pub fn read_into(address: u32, buffer: &mut [u8]) {
+    copy(address, linear_address(buffer.as_ptr() as usize), buffer.len() as u32);
+}
+
+/// Copies bytes to a linear address.
+///
+/// # Panics
+///
+/// If unreal mode isn't enabled.
+pub fn write_from(address: u32, bytes: &[u8]) {
+    copy(linear_address(bytes.as_ptr() as usize), address, bytes.len() as u32);
+}
+
+/// Copies bytes forwards between linear addresses, so overlapping ranges are
+/// only copied correctly if the destination comes first.
+///
+/// # Panics
+///
+/// If unreal mode isn't enabled.
+pub fn copy(source: u32, destination: u32, length: u32) {
+    assert!(is_enabled());
+    // SI can't be given to the compiler as an operand, so it is saved and
+    // loaded by hand.
+    unsafe {
+        asm!(
+            "push es",
+            "push fs",
+            "push esi",
+            "mov esi, eax",
+            "xor ax, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "rep movsb byte ptr es:[edi], byte ptr fs:[esi]",
+            "pop esi",
+            "pop fs",
+            "pop es",
+            inout("eax") source => _,
+            inout("edi") destination => _,
+            inout("ecx") length => _,
+        );
+    }
+}
+
+/// Sets bytes starting at a linear address.
+///
+/// # Panics
+///
+/// If unreal mode isn't enabled.
+pub fn fill(address: u32, value: u8, length: u32) {
+    assert!(is_enabled());
+    unsafe {
+        asm!(
+            "push es",
+            "xor dx, dx",
+            "mov es, dx",
+            "rep stosb byte ptr es:[edi], al",
+            "pop es",
+            inout("edi") address => _,
+            inout("ecx") length => _,
+            in("al") value,
+            out("dx") _,
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use core::arch::asm;
+use crate::far::Segment;
 use crate::port;
 
 /// Fills the entire screen with the specified color in VGA mode 13h.
@@ -6,19 +7,9 @@ use crate::port;
 /// # Arguments
 ///
 /// * `color` - The palette index (0-255) to fill the screen with
+#[allow(dead_code)]
 pub fn fill_screen(color: u8) {
-    unsafe {
-        asm!(
-            "mov   es, ax",
-            "xor   di, di",
-            "mov   cx, 320*200/2",
-            "mov   al, dl",
-            "mov   ah, al",
-            "rep   stosw",
-            inout("ax") 0xA000 => _,
-            in("dl") color,
-        )
-    }
+    Segment::new(0xA000).slice(0, 320 * 200).fill(color);
 }
 
 /// Plots a single pixel at the specified coordinates.
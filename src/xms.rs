@@ -0,0 +1,343 @@
+//! Extended memory through an XMS driver
+//!
+//! HIMEM.SYS and other drivers implementing the eXtended Memory
+//! Specification hand out blocks of memory above 1 MiB, and copy data between
+//! them and conventional memory. The driver isn't called through an interrupt
+//! but through a far entry point, which INT 2Fh function 4310h returns and
+//! [`init`] looks up.
+//!
+//! Blocks are measured in KiB. Data is moved in and out with the driver's
+//! move function, which works in virtual 8086 mode too, or a block can be
+//! locked to get its linear address for use with [`crate::unreal`].
+
+#![allow(dead_code)]
+
+use core::arch::asm;
+use core::ptr::{addr_of, read_volatile};
+use crate::far::FarPtr;
+use crate::interrupts::FarAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// There is no XMS driver.
+    NotInstalled,
+    /// The driver doesn't implement the function.
+    NotImplemented,
+    /// There isn't enough free extended memory.
+    OutOfMemory,
+    /// The driver has run out of handles.
+    OutOfHandles,
+    /// The handle isn't an allocated block.
+    InvalidHandle,
+    /// An offset or length is outside of a block, or the length is odd.
+    InvalidRange,
+    /// The block is locked, so it can't be freed or resized.
+    Locked,
+    /// The block has been locked too many times, or can't be locked.
+    LockFailed,
+    /// Some other XMS error code.
+    Other(u8),
+}
+
+impl Error {
+    fn from_xms(code: u8) -> Self {
+        match code {
+            0x80 => Error::NotImplemented,
+            0xA0 => Error::OutOfMemory,
+            0xA1 => Error::OutOfHandles,
+            0xA2 | 0xA3 | 0xA5 => Error::InvalidHandle,
+            0xA4 | 0xA6 | 0xA7 | 0xA8 => Error::InvalidRange,
+            0xAB => Error::Locked,
+            0xAC | 0xAD => Error::LockFailed,
+            _ => Error::Other(code),
+        }
+    }
+}
+
+/// The driver's entry point, called through by [`call`].
+static mut ENTRY: FarAddress = FarAddress::NULL;
+static mut INSTALLED: Option<bool> = None;
+
+/// The registers that driver functions return results in.
+#[derive(Clone, Copy)]
+struct Registers {
+    ax: u16,
+    bx: u16,
+    dx: u16,
+}
+
+/// Describes a copy for the move function. A handle of 0 means that the
+/// offset is a segment:offset pointer into conventional memory.
+#[repr(C, packed)]
+struct Move {
+    length: u32,
+    source_handle: u16,
+    source_offset: u32,
+    destination_handle: u16,
+    destination_offset: u32,
+}
+
+/// Looks for an XMS driver and remembers its entry point.
+///
+/// # Returns
+///
+/// Whether a driver is installed
+pub fn init() -> bool {
+    let installed: u16;
+    let segment: u16;
+    let offset: u16;
+    unsafe {
+        asm!(
+            "int 2Fh",
+            inout("ax") 0x4300u16 => installed,
+        );
+        let found = installed as u8 == 0x80;
+        if found {
+            asm!(
+                "push es",
+                "push bx",
+                "int 2Fh",
+                "mov cx, es",
+                "mov dx, bx",
+                "pop bx",
+                "pop es",
+                inout("ax") 0x4310u16 => _,
+                out("cx") segment,
+                out("dx") offset,
+            );
+            ENTRY = FarAddress { offset, segment };
+        }
+        INSTALLED = Some(found);
+        found
+    }
+}
+
+/// Returns whether an XMS driver is installed, looking for one if [`init`]
+/// hasn't been called.
+pub fn is_installed() -> bool {
+    match unsafe { read_volatile(addr_of!(INSTALLED)) } {
+        Some(installed) => installed,
+        None => init(),
+    }
+}
+
+/// Calls the driver with AH set to `function`.
+///
+/// BX and SI can't be given to the compiler as operands, so they are passed in
+/// DI and CX and swapped in by hand.
+fn call(function: u8, bx: u16, dx: u16, si: u16) -> Result<Registers, Error> {
+    if !is_installed() {
+        return Err(Error::NotInstalled);
+    }
+    let ax: u16;
+    let bx_out: u16;
+    let dx_out: u16;
+    unsafe {
+        asm!(
+            "push bx",
+            "push esi",
+            "mov bx, di",
+            "mov si, cx",
+            "lcall dword ptr [{entry}]",
+            "mov di, bx",
+            "pop esi",
+            "pop bx",
+            entry = sym ENTRY,
+            inout("ax") (function as u16) << 8 => ax,
+            inout("di") bx => bx_out,
+            inout("dx") dx => dx_out,
+            inout("cx") si => _,
+        );
+    }
+    let registers = Registers { ax, bx: bx_out, dx: dx_out };
+    // Most functions return 1 in AX for success and an error code in BL, but
+    // the query functions return results in AX and only set BL on failure,
+    // which is why BL is passed in as 0 for them.
+    match function {
+        0x00 | 0x08 if bx_out as u8 == 0 || ax != 0 => Ok(registers),
+        0x00 | 0x08 => Err(Error::from_xms(bx_out as u8)),
+        _ if ax == 1 => Ok(registers),
+        _ => Err(Error::from_xms(bx_out as u8)),
+    }
+}
+
+/// Returns the XMS version that the driver implements, in BCD, such as 0x0300
+/// for 3.0.
+pub fn version() -> Result<u16, Error> {
+    call(0x00, 0, 0, 0).map(|registers| registers.ax)
+}
+
+/// Returns the size of the largest free block and the total free extended
+/// memory in KiB.
+pub fn free_memory() -> Result<(u16, u16), Error> {
+    call(0x08, 0, 0, 0).map(|registers| (registers.ax, registers.dx))
+}
+
+/// Asks the driver to enable A20 for the program.
+///
+/// This is the local enable function. The global one is reserved for whoever
+/// owns the high memory area.
+pub fn enable_a20() -> Result<(), Error> {
+    call(0x05, 0, 0, 0).map(|_| ())
+}
+
+/// Undoes one call to [`enable_a20`]. The driver leaves A20 enabled if
+/// anyone else needs it.
+pub fn disable_a20() -> Result<(), Error> {
+    call(0x06, 0, 0, 0).map(|_| ())
+}
+
+/// Copies `length` bytes, which must be even, with the driver's move function.
+fn move_block(source_handle: u16, source_offset: u32, destination_handle: u16, destination_offset: u32, length: u32) -> Result<(), Error> {
+    let request = Move { length, source_handle, source_offset, destination_handle, destination_offset };
+    call(0x0B, 0, 0, &request as *const Move as usize as u16).map(|_| ())
+}
+
+/// Encodes a far pointer the way the move function expects conventional
+/// memory addresses.
+fn conventional(pointer: FarPtr<u8>) -> u32 {
+    (pointer.segment().value() as u32) << 16 | pointer.offset() as u32
+}
+
+/// A block of extended memory, freed when dropped.
+pub struct XmsBlock {
+    handle: u16,
+    kilobytes: u16,
+    locks: u16,
+}
+
+impl XmsBlock {
+    /// Allocates a block of the given size in KiB.
+    pub fn allocate(kilobytes: u16) -> Result<Self, Error> {
+        let registers = call(0x09, 0, kilobytes, 0)?;
+        Ok(XmsBlock { handle: registers.dx, kilobytes, locks: 0 })
+    }
+
+    /// Allocates a block big enough for the given number of bytes.
+    pub fn allocate_bytes(bytes: u32) -> Result<Self, Error> {
+        let kilobytes = bytes.div_ceil(1024);
+        if kilobytes > 0xFFFF {
+            return Err(Error::OutOfMemory);
+        }
+        XmsBlock::allocate(kilobytes as u16)
+    }
+
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    pub fn kilobytes(&self) -> u16 {
+        self.kilobytes
+    }
+
+    /// The size of the block in bytes.
+    pub fn len(&self) -> u32 {
+        self.kilobytes as u32 * 1024
+    }
+
+    /// Grows or shrinks the block, which must not be locked.
+    pub fn resize(&mut self, kilobytes: u16) -> Result<(), Error> {
+        call(0x0F, kilobytes, self.handle, 0)?;
+        self.kilobytes = kilobytes;
+        Ok(())
+    }
+
+    /// Locks the block in place, so that it can be accessed directly through
+    /// [`crate::unreal`].
+    ///
+    /// # Returns
+    ///
+    /// The linear address of the block
+    pub fn lock(&mut self) -> Result<u32, Error> {
+        let registers = call(0x0C, 0, self.handle, 0)?;
+        self.locks += 1;
+        Ok((registers.dx as u32) << 16 | registers.bx as u32)
+    }
+
+    /// Undoes one call to [`XmsBlock::lock`].
+    pub fn unlock(&mut self) -> Result<(), Error> {
+        call(0x0D, 0, self.handle, 0)?;
+        self.locks = self.locks.saturating_sub(1);
+        Ok(())
+    }
+
+    /// Copies `length` bytes from conventional memory into the block at
+    /// `offset`.
+    pub fn copy_from(&mut self, offset: u32, source: FarPtr<u8>, length: u32) -> Result<(), Error> {
+        self.check_range(offset, length)?;
+        let even = length & !1;
+        if even != 0 {
+            move_block(0, conventional(source), self.handle, offset, even)?;
+        }
+        if length & 1 != 0 {
+            // The move function only copies whole words, so the last byte is
+            // merged into a word read back from the block.
+            let last = offset + even;
+            let (start, index) = self.word_around(last);
+            let mut word = [0u8; 2];
+            self.move_out(start, &mut word)?;
+            word[index] = FarPtr::<u8>::from_linear(source.linear() + even).read();
+            self.move_in(start, &word)?;
+        }
+        Ok(())
+    }
+
+    /// Copies `length` bytes from the block at `offset` into conventional
+    /// memory.
+    pub fn copy_to(&self, offset: u32, destination: FarPtr<u8>, length: u32) -> Result<(), Error> {
+        self.check_range(offset, length)?;
+        let even = length & !1;
+        if even != 0 {
+            move_block(self.handle, offset, 0, conventional(destination), even)?;
+        }
+        if length & 1 != 0 {
+            let (start, index) = self.word_around(offset + even);
+            let mut word = [0u8; 2];
+            self.move_out(start, &mut word)?;
+            FarPtr::<u8>::from_linear(destination.linear() + even).write(word[index]);
+        }
+        Ok(())
+    }
+
+    /// Copies bytes into the block at `offset`.
+    pub fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+        self.copy_from(offset, FarPtr::from_near(bytes.as_ptr()), bytes.len() as u32)
+    }
+
+    /// Copies bytes out of the block at `offset`, filling all of `buffer`.
+    pub fn read(&self, offset: u32, buffer: &mut [u8]) -> Result<(), Error> {
+        self.copy_to(offset, FarPtr::from_near(buffer.as_ptr()), buffer.len() as u32)
+    }
+
+    fn check_range(&self, offset: u32, length: u32) -> Result<(), Error> {
+        match offset.checked_add(length) {
+            Some(end) if end <= self.len() => Ok(()),
+            _ => Err(Error::InvalidRange),
+        }
+    }
+
+    /// Returns the start of a word within the block that contains the byte at
+    /// `offset`, and the byte's index within it.
+    fn word_around(&self, offset: u32) -> (u32, usize) {
+        if offset + 1 < self.len() { (offset, 0) } else { (offset - 1, 1) }
+    }
+
+    fn move_out(&self, offset: u32, word: &mut [u8; 2]) -> Result<(), Error> {
+        let destination = conventional(FarPtr::from_near(word.as_ptr()));
+        move_block(self.handle, offset, 0, destination, 2)
+    }
+
+    fn move_in(&mut self, offset: u32, word: &[u8; 2]) -> Result<(), Error> {
+        let source = conventional(FarPtr::from_near(word.as_ptr()));
+        move_block(0, source, self.handle, offset, 2)
+    }
+}
+
+impl Drop for XmsBlock {
+    fn drop(&mut self) {
+        for _ in 0..self.locks {
+            let _ = call(0x0D, 0, self.handle, 0);
+        }
+        let _ = call(0x0A, 0, self.handle, 0);
+    }
+}